@@ -1,20 +1,32 @@
 use anyhow::{bail, Context, Result};
 use camino::Utf8Path;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::env;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::Path;
 use std::process::Command;
 use tracing_subscriber::EnvFilter;
-use weaveffi_core::codegen::{Orchestrator, WasmGenerator};
+use weaveffi_core::codegen::{Generator, Orchestrator, WasmGenerator};
+use weaveffi_core::output::{FileChange, Plan, PlannedFile};
+use weaveffi_core::config::GeneratorOptions;
+use weaveffi_core::plugin::PluginGenerator;
+use weaveffi_core::render::check_overrides;
+use object::read::archive::ArchiveFile;
+use object::{BinaryFormat, Object, ObjectKind, ObjectSymbol};
+use weaveffi_core::c_import::{import_c_header, ImportOptions};
+use weaveffi_core::compat::{check_version_bump, diff_apis};
+use weaveffi_core::verify::verify_symbols;
 use weaveffi_core::validate::validate_api;
+use weaveffi_ir::format::{format_api_str, render_api};
 use weaveffi_ir::parse::parse_api_str;
+use weaveffi_ir::schema::{idl_schema, SCHEMA_VERSION};
 use weaveffi_ir::ir::Api;
 use weaveffi_gen_c::CGenerator;
 use weaveffi_gen_swift::SwiftGenerator;
 use weaveffi_gen_android::AndroidGenerator;
 use weaveffi_gen_node::NodeGenerator;
+use weaveffi_gen_docs::DocsGenerator;
 
 #[derive(Parser, Debug)]
 #[command(name = "weaveffi", version, about = "WeaveFFI CLI")] 
@@ -23,16 +35,88 @@ struct Cli {
     command: Commands,
 }
 
+// Ways to inspect what `generate` would change; any of them leaves the disk untouched
+#[derive(Args, Debug, Clone, Copy)]
+struct Preview {
+    /// List the files that would be created, updated or deleted
+    #[arg(long)] dry_run: bool,
+    /// Show a unified diff against the output directory
+    #[arg(long)] diff: bool,
+    /// Fail if the output directory is out of date with the IDL (for CI)
+    #[arg(long)] check: bool,
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     New { name: String },
+    /// Generate bindings for the selected targets from an IDL file
     Generate {
-        /// Input IDL/IR file (yaml|yml|json|toml)
+        /// Input IDL/IR file (yaml|yml|json|toml|weave|wit)
         input: String,
         /// Output directory for generated artifacts
         #[arg(short, long, default_value = "./generated")] out: String,
+        /// Generate only these targets (c, swift, android, node, wasm or a plugin) and their dependencies
+        #[arg(long = "target", value_delimiter = ',')] targets: Vec<String>,
+        /// Skip these targets
+        #[arg(long, value_delimiter = ',')] exclude: Vec<String>,
+        /// Directory of templates replacing bundled ones, e.g. `c/weaveffi.h.tera`
+        #[arg(long)] templates: Option<String>,
+        #[command(flatten)] preview: Preview,
     },
     Doctor,
+    /// Generate an mdBook API reference with per-language signatures
+    Docs {
+        /// Input IDL/IR file (yaml|yml|json|toml|weave|wit)
+        input: String,
+        /// Output directory for the book
+        #[arg(short, long, default_value = "./site")] out: String,
+        /// Directory of templates replacing bundled ones, e.g. `docs/module.md.tera`
+        #[arg(long)] templates: Option<String>,
+    },
+    /// Print the JSON Schema for IDL files (for editor completion and validation)
+    Schema {
+        /// Write to a file instead of stdout
+        #[arg(short, long)] out: Option<String>,
+    },
+    /// Compare two versions of an IDL and check that `version` was bumped per semver
+    AbiDiff {
+        /// Previously released IDL
+        old: String,
+        /// Candidate IDL
+        new: String,
+    },
+    /// Check that a built library (.so, .dylib, .dll or .a) exports every symbol the IDL declares
+    VerifyLib {
+        /// Input IDL/IR file
+        input: String,
+        /// Shared or static library to inspect
+        lib: String,
+    },
+    /// Rewrite IDL files in canonical layout, or convert them to another format
+    Fmt {
+        /// IDL files to format (yml|yaml|json|toml)
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Report files that are not formatted instead of rewriting them
+        #[arg(long)] check: bool,
+        /// Convert to this format (yaml|json|toml), writing a sibling file with the new extension
+        #[arg(long)] to: Option<String>,
+    },
+    /// Run a language server for IDL files over stdio (diagnostics, definitions, hover, rename)
+    Lsp,
+    /// Import the `weaveffi_<module>_<function>` prototypes of a C header as an IDL
+    ImportC {
+        /// C header to import
+        header: String,
+        /// Write the IDL to this file (yml|yaml|json|toml) instead of printing YAML
+        #[arg(short, long)] out: Option<String>,
+        /// Module whose name contains `_`, e.g. `image_io`; repeat for several
+        #[arg(long = "module")] modules: Vec<String>,
+        /// Import `<prefix><function>` prototypes into a module, e.g. `calc_=calculator`; repeat for several
+        #[arg(long = "prefix", value_parser = parse_prefix)] prefixes: Vec<(String, String)>,
+        /// Write the C adapter that implements the `weaveffi_*` entry points of prefixed functions to this file
+        #[arg(long)] adapter: Option<String>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -40,13 +124,23 @@ fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .without_time()
+        .with_writer(std::io::stderr)
         .init();
 
     let cli = Cli::parse();
     match cli.command {
         Commands::New { name } => cmd_new(&name)?,
-        Commands::Generate { input, out } => cmd_generate(&input, &out)?,
+        Commands::Generate { input, out, targets, exclude, templates, preview } => cmd_generate(&input, &out, &targets, &exclude, templates.as_deref(), preview)?,
         Commands::Doctor => cmd_doctor()?,
+        Commands::Docs { input, out, templates } => cmd_docs(&input, &out, templates.as_deref())?,
+        Commands::Schema { out } => cmd_schema(out.as_deref())?,
+        Commands::AbiDiff { old, new } => cmd_abi_diff(&old, &new)?,
+        Commands::VerifyLib { input, lib } => cmd_verify_lib(&input, &lib)?,
+        Commands::Fmt { inputs, check, to } => cmd_fmt(&inputs, check, to.as_deref())?,
+        Commands::Lsp => weaveffi_lsp::run_stdio()?,
+        Commands::ImportC { header, out, modules, prefixes, adapter } => {
+            cmd_import_c(&header, out.as_deref(), adapter.as_deref(), ImportOptions { modules, prefixes })?
+        }
     }
     Ok(())
 }
@@ -63,6 +157,7 @@ fn cmd_new(name: &str) -> Result<()> {
     let idl_path = project_dir.join("weaveffi.yml");
     let idl_contents = format!(
         concat!(
+            "# yaml-language-server: $schema=./weaveffi.schema.json\n",
             "version: \"0.1.0\"\n",
             "modules:\n",
             "  - name: {module}\n",
@@ -85,13 +180,17 @@ fn cmd_new(name: &str) -> Result<()> {
         module = module_name
     );
     fs::write(&idl_path, idl_contents).with_context(|| format!("failed to write {}", idl_path.display()))?;
+    let schema_path = project_dir.join("weaveffi.schema.json");
+    fs::write(&schema_path, serde_json::to_string_pretty(&idl_schema())? + "\n")
+        .with_context(|| format!("failed to write {}", schema_path.display()))?;
 
     let readme_path = project_dir.join("README.md");
     let readme = format!(
         concat!(
             "# {name}\n\n",
             "This project was bootstrapped with WeaveFFI.\n\n",
-            "- Edit `weaveffi.yml` to define your API.\n",
+            "- Edit `weaveffi.yml` to define your API. `weaveffi.schema.json` gives editors with\n",
+            "  yaml-language-server completion and validation; refresh it with `weaveffi schema -o weaveffi.schema.json`.\n",
             "- Generate outputs: `weaveffi generate weaveffi.yml -o ../generated` (or choose any out dir).\n",
             "- See docs for memory/error model and platform specifics.\n"
         ),
@@ -105,34 +204,319 @@ fn cmd_new(name: &str) -> Result<()> {
     Ok(())
 }
 
-fn cmd_generate(input: &str, out: &str) -> Result<()> {
-    let in_path = std::path::Path::new(input);
-    let ext = in_path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    let format = match ext {
+/// IDL format of a file, from its extension.
+fn idl_format(path: &Path) -> Result<&'static str> {
+    Ok(match path.extension().and_then(|e| e.to_str()).unwrap_or("") {
         "yml" | "yaml" => "yaml",
         "json" => "json",
         "toml" => "toml",
-        other => bail!("unsupported input format: {} (expected yml|yaml|json|toml)", other),
-    };
+        "weave" => "weave",
+        "wit" => "wit",
+        other => bail!("unsupported input format: {} (expected yml|yaml|json|toml|weave|wit)", other),
+    })
+}
+
+fn load_api(input: &str) -> Result<Api> {
+    let in_path = std::path::Path::new(input);
+    let format = idl_format(in_path)?;
     let contents = std::fs::read_to_string(in_path)
         .with_context(|| format!("failed to read input file: {}", input))?;
     let api: Api = parse_api_str(&contents, format)
         .with_context(|| format!("failed to parse {} as {}", input, format))?;
-    validate_api(&api).context("IR validation failed")?;
+    validate_api(&api).with_context(|| format!("IR validation failed for {}", input))?;
+    Ok(api)
+}
+
+/// Targets implemented in this binary; any other name may be a plugin.
+const BUILTIN_TARGETS: &[&str] = &["c", "swift", "android", "node", "wasm"];
+
+fn cmd_generate(input: &str, out: &str, targets: &[String], exclude: &[String], templates: Option<&str>, preview: Preview) -> Result<()> {
+    let api = load_api(input)?;
+    let options = load_options(templates)?;
 
     let out_dir = Utf8Path::new(out);
-    std::fs::create_dir_all(out_dir)
-        .with_context(|| format!("failed to create output directory: {}", out))?;
 
-    let orchestrator = Orchestrator::new()
+    let targets = if targets.is_empty() { options.targets.as_deref() } else { Some(targets) };
+    let mut plugins = options
+        .plugins
+        .iter()
+        .map(|(name, config)| PluginGenerator::configured(name, config))
+        .collect::<Result<Vec<_>>>()?;
+    // Requested targets that are neither built in nor configured may be plugins on PATH
+    for name in targets.unwrap_or_default() {
+        if !BUILTIN_TARGETS.contains(&name.as_str()) && !options.plugins.contains_key(name) {
+            plugins.extend(PluginGenerator::from_path(name));
+        }
+    }
+
+    let mut orchestrator = Orchestrator::new()
         .with_generator(&CGenerator)
         .with_generator(&SwiftGenerator)
         .with_generator(&AndroidGenerator)
         .with_generator(&NodeGenerator)
         .with_generator(&WasmGenerator);
+    for plugin in &plugins {
+        orchestrator = orchestrator.with_generator(plugin);
+    }
+    let orchestrator = orchestrator.select(targets, exclude)?;
+
+    if !(preview.dry_run || preview.diff || preview.check) {
+        let plan = orchestrator.run(&api, &options, out_dir)?;
+        println!("Generated {} in {} ({})", orchestrator.targets().join(", "), out, summarize(&plan));
+        return Ok(());
+    }
+    let plan = orchestrator.render(&api, &options)?.plan(out_dir)?;
+    for file in plan.changes() {
+        if preview.dry_run {
+            let action = match file.change {
+                FileChange::Create => "create",
+                FileChange::Update => "update",
+                FileChange::Delete => "delete",
+                FileChange::Unchanged => continue,
+            };
+            // Keep stdout a clean patch for `git apply`
+            if preview.diff {
+                eprintln!("{} {}", action, out_dir.join(&file.path));
+            } else {
+                println!("{} {}", action, out_dir.join(&file.path));
+            }
+        }
+        if preview.diff {
+            print!("{}", unified_diff(out_dir, file));
+        }
+    }
+    let summary = summarize(&plan);
+    let changed = plan.changes().count();
+    if preview.check && changed > 0 {
+        bail!("{} generated file(s) in {} are out of date; run `weaveffi generate` to update them", changed, out);
+    }
+    eprintln!("Would generate {} in {} ({})", orchestrator.targets().join(", "), out, summary);
+    Ok(())
+}
+
+/// `git diff`-style patch turning the file on disk into the generated one.
+fn unified_diff(out_dir: &Utf8Path, file: &PlannedFile) -> String {
+    let path = out_dir.join(&file.path);
+    let (old_header, new_header) = match file.change {
+        FileChange::Create => ("/dev/null".to_string(), format!("b/{}", path)),
+        FileChange::Delete => (format!("a/{}", path), "/dev/null".to_string()),
+        _ => (format!("a/{}", path), format!("b/{}", path)),
+    };
+    let old = file.old_contents.as_deref().unwrap_or("");
+    let new = file.contents.as_deref().unwrap_or("");
+    similar::TextDiff::from_lines(old, new).unified_diff().header(&old_header, &new_header).to_string()
+}
+
+/// Config from the nearest `weaveffi.toml`, with `--templates` taking
+/// precedence over its `templates` key.
+fn load_options(templates: Option<&str>) -> Result<GeneratorOptions> {
+    let (mut options, path) = GeneratorOptions::discover(&env::current_dir()?)?;
+    if let Some(path) = path {
+        eprintln!("Using config {}", path.display());
+    }
+    if let Some(templates) = templates {
+        options.templates = Some(templates.into());
+    }
+    if let Some(dir) = &options.templates {
+        let generators: [&dyn Generator; 6] = [&CGenerator, &SwiftGenerator, &AndroidGenerator, &NodeGenerator, &WasmGenerator, &DocsGenerator];
+        let known: Vec<&str> = generators.iter().flat_map(|g| g.templates().iter().map(|&(name, _)| name)).collect();
+        check_overrides(dir, &known)?;
+    }
+    Ok(options)
+}
+
+/// Print warnings about hand-edited files and count what `plan` changed.
+fn summarize(plan: &Plan) -> String {
+    for warning in &plan.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let counts = [FileChange::Create, FileChange::Update, FileChange::Delete, FileChange::Unchanged].map(|c| plan.count(c));
+    format!("{} created, {} updated, {} deleted, {} unchanged", counts[0], counts[1], counts[2], counts[3])
+}
+
+fn cmd_docs(input: &str, out: &str, templates: Option<&str>) -> Result<()> {
+    let api = load_api(input)?;
+    let out_dir = Utf8Path::new(out);
+    let plan = Orchestrator::new().with_generator(&DocsGenerator).run(&api, &load_options(templates)?, out_dir)?;
+    println!("Generated API reference in {} ({}); build it with `mdbook build {}`", out, summarize(&plan), out);
+    Ok(())
+}
+
+fn cmd_schema(out: Option<&str>) -> Result<()> {
+    let json = serde_json::to_string_pretty(&idl_schema())? + "\n";
+    match out {
+        Some(path) => {
+            fs::write(path, json).with_context(|| format!("failed to write {}", path))?;
+            println!("Wrote IDL schema {} to {}", SCHEMA_VERSION, path);
+        }
+        None => print!("{}", json),
+    }
+    Ok(())
+}
+
+fn cmd_fmt(inputs: &[String], check: bool, to: Option<&str>) -> Result<()> {
+    let mut unformatted = 0;
+    for input in inputs {
+        let in_path = Path::new(input);
+        let from = idl_format(in_path)?;
+        let (to, out_path) = match to {
+            None => (from, in_path.to_path_buf()),
+            Some(ext) => (idl_format(Path::new(&format!("x.{}", ext)))?, in_path.with_extension(ext)),
+        };
+        let src = fs::read_to_string(in_path).with_context(|| format!("failed to read input file: {}", input))?;
+        let formatted = format_api_str(&src, from, to).with_context(|| format!("failed to format {}", input))?;
+        let current = if out_path == in_path { Some(src) } else { fs::read_to_string(&out_path).ok() };
+        if current.as_deref() == Some(formatted.text.as_str()) {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", out_path.display());
+            unformatted += 1;
+            continue;
+        }
+        fs::write(&out_path, &formatted.text).with_context(|| format!("failed to write {}", out_path.display()))?;
+        if out_path == in_path {
+            println!("Formatted {}", input);
+        } else {
+            println!("Converted {} to {}", input, out_path.display());
+        }
+        if formatted.dropped_comments > 0 {
+            eprintln!("warning: dropped {} comment(s) from {}; {} has no comments", formatted.dropped_comments, input, to);
+        }
+    }
+    if unformatted > 0 {
+        bail!("{} file(s) need formatting; run `weaveffi fmt` to fix", unformatted);
+    }
+    Ok(())
+}
+
+/// `calc_=calculator`; a bare `calc_` names module `calc`.
+fn parse_prefix(s: &str) -> Result<(String, String), String> {
+    let (prefix, module) = match s.split_once('=') {
+        Some((prefix, module)) => (prefix, module.to_string()),
+        None => (s, s.trim_end_matches('_').to_string()),
+    };
+    if prefix.is_empty() || module.is_empty() {
+        return Err(format!("expected PREFIX=MODULE, e.g. `calc_=calculator`, got `{}`", s));
+    }
+    Ok((prefix.to_string(), module))
+}
+
+fn cmd_import_c(header: &str, out: Option<&str>, adapter: Option<&str>, options: ImportOptions) -> Result<()> {
+    let src = fs::read_to_string(header).with_context(|| format!("failed to read header: {}", header))?;
+    let import = import_c_header(&src, &options);
+    for u in &import.unmapped {
+        eprintln!("warning: {}:{}: {}\n    {}", header, u.line, u.reason, u.declaration);
+    }
+    let functions: usize = import.api.modules.iter().map(|m| m.functions.len()).sum();
+    if functions == 0 {
+        bail!("no prototypes in {} could be mapped to `weaveffi_<module>_<function>`", header);
+    }
+    validate_api(&import.api).context("imported API is invalid")?;
+    let to = match out {
+        Some(path) => idl_format(Path::new(path))?,
+        None => "yaml",
+    };
+    let text = render_api(&import.api, to).context("failed to render imported API")?;
+    match out {
+        Some(path) => fs::write(path, text).with_context(|| format!("failed to write {}", path))?,
+        None => print!("{}", text),
+    }
+    let include = Path::new(header).file_name().map_or(header.into(), |n| n.to_string_lossy());
+    match (import.adapter(&include), adapter) {
+        (Some(source), Some(path)) => {
+            fs::write(path, source).with_context(|| format!("failed to write {}", path))?;
+            eprintln!("Wrote the adapter for prefixed functions to {}", path);
+        }
+        (Some(_), None) => eprintln!("note: prefixed functions are called through an adapter; pass --adapter adapter.c to write it"),
+        (None, Some(_)) => eprintln!("note: no prototype matched a --prefix, so there is no adapter to write"),
+        (None, None) => {}
+    }
+    eprintln!(
+        "Imported {} function(s) in {} module(s) from {}; {} declaration(s) could not be mapped",
+        functions,
+        import.api.modules.len(),
+        header,
+        import.unmapped.len()
+    );
+    Ok(())
+}
+
+fn cmd_abi_diff(old: &str, new: &str) -> Result<()> {
+    let old_api = load_api(old)?;
+    let new_api = load_api(new)?;
+    let diff = diff_apis(&old_api, &new_api);
+    if diff.changes.is_empty() {
+        println!("No ABI changes");
+    }
+    for change in &diff.changes {
+        let kind = if change.is_breaking() { "breaking" } else { "compatible" };
+        println!("- [{}] {}", kind, change);
+    }
+    println!("Required bump: {} ({} -> {})", diff.required_bump(), old_api.version, new_api.version);
+    check_version_bump(&old_api, &new_api, &diff).context("version check failed")?;
+    println!("Version bump OK");
+    Ok(())
+}
+
+fn cmd_verify_lib(input: &str, lib: &str) -> Result<()> {
+    let api = load_api(input)?;
+    let data = fs::read(lib).with_context(|| format!("failed to read library: {}", lib))?;
+    let exported = exported_symbols(&data).with_context(|| format!("failed to read symbols from {}", lib))?;
+    let report = verify_symbols(&api, exported.iter().map(String::as_str));
+    for name in &report.missing {
+        println!("- missing: {}", name);
+    }
+    for (expected, found) in &report.misspelled {
+        println!("- misspelled: {} (found {})", expected, found);
+    }
+    for name in &report.extra {
+        println!("- extra: {}", name);
+    }
+    if !report.is_ok() {
+        bail!("{} does not match {}: {} missing, {} misspelled", lib, input, report.missing.len(), report.misspelled.len());
+    }
+    println!("{} exports every symbol declared by {}", lib, input);
+    Ok(())
+}
 
-    orchestrator.run(&api, out_dir)?;
-    println!("Generated artifacts in {}", out);
+/// Defined global symbols of a shared library, or of every object in a static archive.
+fn exported_symbols(data: &[u8]) -> Result<Vec<String>> {
+    let mut out = Vec::new();
+    match ArchiveFile::parse(data) {
+        Ok(archive) => {
+            for member in archive.members() {
+                let bytes = member?.data(data)?;
+                // Archives may carry non-object members, e.g. rlib metadata
+                if let Ok(file) = object::File::parse(bytes) {
+                    collect_symbols(&file, &mut out)?;
+                }
+            }
+        }
+        Err(_) => collect_symbols(&object::File::parse(data)?, &mut out)?,
+    }
+    Ok(out)
+}
+
+fn collect_symbols(file: &object::File, out: &mut Vec<String>) -> Result<()> {
+    let mut push = |name: &[u8]| {
+        let name = String::from_utf8_lossy(name);
+        // Mach-O prefixes C symbols with an underscore
+        let name = if file.format() == BinaryFormat::MachO { name.strip_prefix('_').unwrap_or(&name) } else { &name };
+        out.push(name.to_string());
+    };
+    match file.kind() {
+        ObjectKind::Dynamic | ObjectKind::Executable => {
+            for export in file.exports()? {
+                push(export.name());
+            }
+        }
+        _ => {
+            for sym in file.symbols().filter(|s| s.is_global() && s.is_definition()) {
+                push(sym.name_bytes()?);
+            }
+        }
+    }
     Ok(())
 }
 
@@ -1,9 +1,24 @@
 //! C ABI runtime: error struct, memory helpers, and utility functions.
 #![allow(non_camel_case_types)]
 
-use std::ffi::{CStr, CString};
+use std::alloc::{self, Layout};
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
 use std::ptr;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+
+/// Version of the runtime conventions in this module (error struct, memory
+/// helpers, handle encoding). Bumped whenever generated bindings would break.
+pub const ABI_VERSION: u32 = 1;
+
+/// Error code reported when the host allocator returns null.
+pub const ERROR_ALLOC_FAILED: i32 = -2;
+
+/// Error code reported when `InteriorNul::Reject` refuses a returned string.
+pub const ERROR_INTERIOR_NUL: i32 = -3;
 
 /// Public opaque handle type exposed to foreign callers.
 pub type weaveffi_handle_t = u64;
@@ -11,7 +26,7 @@ pub type weaveffi_handle_t = u64;
 /// Error struct passed across the C ABI boundary.
 ///
 /// Safety:
-/// - `message` is a NUL-terminated UTF-8 C string allocated by Rust and must be
+/// - `message` is a NUL-terminated UTF-8 C string allocated by the runtime and must be
 ///   released by calling `weaveffi_error_clear` or `weaveffi_free_string`.
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -26,37 +41,180 @@ impl Default for weaveffi_error {
     }
 }
 
+/// Host allocation callback: return `size` bytes or null on failure.
+pub type weaveffi_malloc_fn = Option<unsafe extern "C" fn(size: usize, user_data: *mut c_void) -> *mut c_void>;
+/// Host deallocation callback; `size` is the size originally requested.
+pub type weaveffi_free_fn = Option<unsafe extern "C" fn(ptr: *mut c_void, size: usize, user_data: *mut c_void)>;
+
+#[derive(Clone, Copy)]
+struct HostAllocator {
+    malloc: unsafe extern "C" fn(usize, *mut c_void) -> *mut c_void,
+    free: unsafe extern "C" fn(*mut c_void, usize, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// SAFETY: the host promises its callbacks may be invoked from any thread.
+unsafe impl Send for HostAllocator {}
+unsafe impl Sync for HostAllocator {}
+
+/// `None` means the Rust global allocator. Fixed by the first allocation or
+/// by `set_allocator`, whichever comes first.
+static ALLOCATOR: OnceLock<Option<HostAllocator>> = OnceLock::new();
+
+fn allocator() -> Option<HostAllocator> { *ALLOCATOR.get_or_init(|| None) }
+
+/// Route every string, buffer and error message returned to foreign code
+/// through host-supplied callbacks. Must be called before any allocation;
+/// returns `false` if the allocator is already fixed or either callback is null.
+///
+/// # Safety
+/// `malloc_fn` and `free_fn` must behave like `malloc` and a sized `free` for
+/// the rest of the process, from any thread, and accept `user_data`.
+pub unsafe fn set_allocator(malloc_fn: weaveffi_malloc_fn, free_fn: weaveffi_free_fn, user_data: *mut c_void) -> bool {
+    let (Some(malloc), Some(free)) = (malloc_fn, free_fn) else { return false };
+    ALLOCATOR.set(Some(HostAllocator { malloc, free, user_data })).is_ok()
+}
+
+/// Copy `bytes` into a fresh buffer from the active allocator, optionally
+/// appending a trailing NUL. Empty, non-terminated buffers are dangling.
+/// Returns null if the host allocator fails.
+fn alloc_copy(bytes: &[u8], nul: bool) -> *mut u8 {
+    let size = bytes.len() + usize::from(nul);
+    if size == 0 { return ptr::NonNull::dangling().as_ptr(); }
+    let layout = Layout::array::<u8>(size).expect("allocation size overflow");
+    let buf = match allocator() {
+        Some(a) => {
+            // SAFETY: callback contract is documented on `weaveffi_set_allocator`
+            let buf = unsafe { (a.malloc)(size, a.user_data) as *mut u8 };
+            if buf.is_null() { return ptr::null_mut(); }
+            buf
+        }
+        None => {
+            // SAFETY: `layout` has a non-zero size
+            let buf = unsafe { alloc::alloc(layout) };
+            if buf.is_null() { alloc::handle_alloc_error(layout); }
+            buf
+        }
+    };
+    // SAFETY: `buf` holds `size` writable bytes and does not overlap `bytes`
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len());
+        if nul { *buf.add(bytes.len()) = 0; }
+    }
+    buf
+}
+
+/// Release a buffer from `alloc_copy`; `size` includes any trailing NUL.
+fn free_alloc(ptr_: *mut u8, size: usize) {
+    if ptr_.is_null() || size == 0 { return; }
+    match allocator() {
+        // SAFETY: the buffer came from the same host `malloc` with this size
+        Some(a) => unsafe { (a.free)(ptr_ as *mut c_void, size, a.user_data) },
+        // SAFETY: the buffer came from `alloc::alloc` with this layout
+        None => unsafe { alloc::dealloc(ptr_, Layout::array::<u8>(size).expect("allocation size overflow")) },
+    }
+}
+
+fn alloc_c_string(bytes: &[u8]) -> *const c_char { alloc_copy(bytes, true) as *const c_char }
+
+/// `alloc_copy` for a string or buffer return, reporting a host allocator
+/// failure as `ERROR_ALLOC_FAILED` through `fail_return`.
+///
+/// # Safety
+/// Same as `error_set`.
+unsafe fn alloc_return(bytes: &[u8], nul: bool, out_err: *mut weaveffi_error) -> *mut u8 {
+    let buf = alloc_copy(bytes, nul);
+    if buf.is_null() {
+        let size = bytes.len() + usize::from(nul);
+        // SAFETY: forwarded from the caller
+        unsafe { fail_return(out_err, ERROR_ALLOC_FAILED, &format!("host allocator failed to allocate {} bytes", size)) };
+    }
+    buf
+}
+
+/// Write `len` to `out_len`, or 0 when the return failed with `ptr_` null.
+///
+/// # Safety
+/// `out_len` must be null or valid for writes.
+unsafe fn set_out_len<T>(out_len: *mut usize, ptr_: *const T, len: usize) {
+    if out_len.is_null() { return; }
+    // SAFETY: Pointer checked for null above
+    unsafe { *out_len = if ptr_.is_null() { 0 } else { len } };
+}
+
+fn free_c_string(ptr_: *const c_char) {
+    if ptr_.is_null() { return; }
+    // SAFETY: pointer was produced by `alloc_c_string` and is NUL-terminated
+    let len = unsafe { CStr::from_ptr(ptr_) }.to_bytes().len();
+    free_alloc(ptr_ as *mut u8, len + 1);
+}
+
+/// What NUL-terminated returns do with interior NULs, which C strings cannot carry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InteriorNul {
+    /// Drop them (default)
+    #[default]
+    Strip,
+    /// Fail the returned string with `ERROR_INTERIOR_NUL`; error messages show
+    /// them as U+FFFD, since reporting an error cannot itself fail
+    Reject,
+}
+
+static REJECT_INTERIOR_NUL: AtomicBool = AtomicBool::new(false);
+
+/// Choose how `string_to_c_ptr`, `error_set` and `last_error_set` treat
+/// interior NULs for the whole process. `out_len` strings always keep them.
+pub fn set_interior_nul(policy: InteriorNul) {
+    REJECT_INTERIOR_NUL.store(policy == InteriorNul::Reject, Ordering::Relaxed);
+}
+
+pub fn interior_nul() -> InteriorNul {
+    if REJECT_INTERIOR_NUL.load(Ordering::Relaxed) { InteriorNul::Reject } else { InteriorNul::Strip }
+}
+
+/// `message` without interior NULs, following the `InteriorNul` policy.
+fn sanitize_message(message: &str) -> Cow<'_, str> {
+    if !message.contains('\0') { return Cow::Borrowed(message); }
+    match interior_nul() {
+        InteriorNul::Strip => Cow::Owned(message.replace('\0', "")),
+        InteriorNul::Reject => Cow::Owned(message.replace('\0', "\u{fffd}")),
+    }
+}
+
 /// Set the error to OK (code = 0) and free any prior message.
-pub fn error_set_ok(out_err: *mut weaveffi_error) {
+///
+/// # Safety
+/// `out_err` must be null or point to a valid `weaveffi_error` whose message,
+/// if any, came from this runtime.
+pub unsafe fn error_set_ok(out_err: *mut weaveffi_error) {
     if out_err.is_null() { return; }
     // SAFETY: Pointer checked for null above
     let err = unsafe { &mut *out_err };
-    if !err.message.is_null() {
-        // SAFETY: Message was allocated via `CString::into_raw` in this module
-        unsafe { drop(CString::from_raw(err.message as *mut c_char)) };
-    }
+    free_c_string(err.message);
     err.code = 0;
     err.message = ptr::null();
 }
 
 /// Populate an error with the given code and message (copying message).
-pub fn error_set(out_err: *mut weaveffi_error, code: i32, message: &str) {
+///
+/// # Safety
+/// `out_err` must be null or point to a valid `weaveffi_error` whose message,
+/// if any, came from this runtime.
+pub unsafe fn error_set(out_err: *mut weaveffi_error, code: i32, message: &str) {
     if out_err.is_null() { return; }
     // SAFETY: Pointer checked for null above
     let err = unsafe { &mut *out_err };
-    if !err.message.is_null() {
-        // SAFETY: Message was allocated via `CString::into_raw` in this module
-        unsafe { drop(CString::from_raw(err.message as *mut c_char)) };
-    }
+    free_c_string(err.message);
     err.code = code;
-    // `CString::new` fails if message contains interior NULs; replace them defensively
-    let owned_message = message.replace('\0', "");
-    let cstr = CString::new(owned_message).expect("CString::new sanitized input");
-    err.message = cstr.into_raw();
+    // Interior NULs would truncate the C string
+    err.message = alloc_c_string(sanitize_message(message).as_bytes());
 }
 
 /// Convenience adapter: map a `Result<T, E>` to `Option<T>` by writing into `out_err`.
-pub fn result_to_out_err<T, E: std::fmt::Display>(result: Result<T, E>, out_err: *mut weaveffi_error) -> Option<T> {
+///
+/// # Safety
+/// Same as `error_set`.
+pub unsafe fn result_to_out_err<T, E: std::fmt::Display>(result: Result<T, E>, out_err: *mut weaveffi_error) -> Option<T> {
     match result {
         Ok(value) => {
             error_set_ok(out_err);
@@ -71,39 +229,405 @@ pub fn result_to_out_err<T, E: std::fmt::Display>(result: Result<T, E>, out_err:
 }
 
 /// Allocate a new C string from a Rust string, returning an owned pointer.
+/// Interior NULs are handled per `set_interior_nul`: stripped, or rejected by
+/// returning null with `ERROR_INTERIOR_NUL` reported through `fail_return`.
+/// A host allocator failure returns null with `ERROR_ALLOC_FAILED`.
 /// Caller must later free with `weaveffi_free_string` or `weaveffi_error_clear`.
-pub fn string_to_c_ptr(s: impl AsRef<str>) -> *const c_char {
+///
+/// # Safety
+/// Same as `error_set`.
+pub unsafe fn string_to_c_ptr(s: impl AsRef<str>, out_err: *mut weaveffi_error) -> *const c_char {
     let s = s.as_ref();
-    // Sanitize interior NULs which are invalid for C strings
-    let sanitized = if s.as_bytes().contains(&0) { s.replace('\0', "") } else { s.to_owned() };
-    let cstr = CString::new(sanitized).expect("string_to_c_ptr: unexpected NUL after sanitization");
-    cstr.into_raw()
+    if !s.as_bytes().contains(&0) {
+        // SAFETY: forwarded from the caller
+        return unsafe { alloc_return(s.as_bytes(), true, out_err) as *const c_char };
+    }
+    match interior_nul() {
+        // SAFETY: forwarded from the caller
+        InteriorNul::Strip => unsafe { alloc_return(s.replace('\0', "").as_bytes(), true, out_err) as *const c_char },
+        InteriorNul::Reject => {
+            // SAFETY: forwarded from the caller
+            unsafe { fail_return(out_err, ERROR_INTERIOR_NUL, "returned string contains an interior NUL") };
+            ptr::null()
+        }
+    }
+}
+
+/// Report why a string or buffer return is null: through `out_err`, or as the
+/// thread's last error when `out_err` is null (the `last_error` convention).
+///
+/// # Safety
+/// Same as `error_set`.
+unsafe fn fail_return(out_err: *mut weaveffi_error, code: i32, message: &str) {
+    if out_err.is_null() {
+        last_error_set(code, message);
+    } else {
+        // SAFETY: forwarded from the caller
+        unsafe { error_set(out_err, code, message) };
+    }
+}
+
+/// Like `string_to_c_ptr`, but reports interior NULs as an error instead of
+/// stripping them, for callers that would rather fail than corrupt data.
+/// A host allocator failure returns `Ok(null)` with `ERROR_ALLOC_FAILED` as the
+/// thread's last error.
+pub fn try_string_to_c_ptr(s: impl AsRef<str>) -> Result<*const c_char, std::ffi::NulError> {
+    let cstr = CString::new(s.as_ref())?;
+    // SAFETY: a null `out_err` is always valid
+    Ok(unsafe { alloc_return(cstr.as_bytes(), true, ptr::null_mut()) as *const c_char })
+}
+
+/// Allocate a string for the `out_len` string convention. Interior NULs are
+/// preserved; the buffer is still NUL-terminated (not counted in `out_len`).
+/// A host allocator failure returns null with `out_len` 0 and
+/// `ERROR_ALLOC_FAILED` reported through `fail_return`.
+/// Caller must later free with `weaveffi_free_string_len(ptr, len)`.
+///
+/// # Safety
+/// `out_len` must be null or valid for writes; `out_err` as for `error_set`.
+pub unsafe fn string_to_c_ptr_with_len(s: impl AsRef<str>, out_len: *mut usize, out_err: *mut weaveffi_error) -> *const c_char {
+    let bytes = s.as_ref().as_bytes();
+    // SAFETY: forwarded from the caller
+    unsafe {
+        let ptr_ = alloc_return(bytes, true, out_err) as *const c_char;
+        set_out_len(out_len, ptr_, bytes.len());
+        ptr_
+    }
+}
+
+/// Free a string returned by `string_to_c_ptr_with_len`; `len` is the value
+/// that was written to `out_len`.
+///
+/// # Safety
+/// `ptr_` must be null or a string from `string_to_c_ptr_with_len` that
+/// reported `len`, and must not be used afterwards.
+pub unsafe fn free_string_len(ptr_: *const c_char, len: usize) {
+    free_alloc(ptr_ as *mut u8, len + 1);
 }
 
 /// Free a C string previously allocated by this runtime.
-pub fn free_string(ptr_: *const c_char) {
-    if ptr_.is_null() { return; }
-    // SAFETY: Pointer must be returned from `CString::into_raw`
-    unsafe { drop(CString::from_raw(ptr_ as *mut c_char)) };
+///
+/// # Safety
+/// `ptr_` must be null or a string from this runtime that was not freed yet.
+pub unsafe fn free_string(ptr_: *const c_char) { free_c_string(ptr_); }
+
+/// Hand a byte buffer to foreign code, writing its length to `out_len`. The
+/// buffer is handed over without copying unless a host allocator is installed;
+/// if that fails, returns null with `out_len` 0 and `ERROR_ALLOC_FAILED`
+/// reported through `fail_return`.
+/// Caller must later free with `weaveffi_free_bytes(ptr, len)`.
+///
+/// # Safety
+/// `out_len` must be null or valid for writes; `out_err` as for `error_set`.
+pub unsafe fn bytes_to_c_ptr(bytes: impl Into<Box<[u8]>>, out_len: *mut usize, out_err: *mut weaveffi_error) -> *const u8 {
+    let boxed: Box<[u8]> = bytes.into();
+    let len = boxed.len();
+    let ptr_ = match allocator() {
+        // SAFETY: forwarded from the caller
+        Some(_) => unsafe { alloc_return(&boxed, false, out_err) as *const u8 },
+        None => Box::into_raw(boxed) as *const u8,
+    };
+    // SAFETY: forwarded from the caller
+    unsafe { set_out_len(out_len, ptr_, len) };
+    ptr_
 }
 
-/// Free a byte buffer previously allocated by Rust and returned to foreign code.
-/// The buffer must have been allocated as a single contiguous block and its
-/// length passed back alongside the pointer.
-pub fn free_bytes(ptr: *mut u8, len: usize) {
+/// Free a byte buffer previously returned by `bytes_to_c_ptr`; `len` is the
+/// value that was written to `out_len`.
+///
+/// # Safety
+/// `ptr` must be null or a buffer from `bytes_to_c_ptr` that reported `len`,
+/// and must not be used afterwards.
+pub unsafe fn free_bytes(ptr: *mut u8, len: usize) {
     if ptr.is_null() { return; }
-    // SAFETY: This reconstructs the original Box<[u8]> for deallocation
-    unsafe { drop(Box::from_raw(std::slice::from_raw_parts_mut(ptr, len))) };
+    match allocator() {
+        Some(_) => free_alloc(ptr, len),
+        // SAFETY: This reconstructs the Box<[u8]> leaked by `bytes_to_c_ptr`
+        None => unsafe { drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len))) },
+    }
 }
 
 /// Clear an error by freeing any message and zeroing fields.
-pub fn error_clear(err: *mut weaveffi_error) { error_set_ok(err); }
+///
+/// # Safety
+/// Same as `error_set_ok`.
+pub unsafe fn error_clear(err: *mut weaveffi_error) { error_set_ok(err); }
+
+/// Borrow a `(ptr, len)` parameter without copying. A null `ptr` yields an
+/// empty slice. The slice must not be retained past the call.
+///
+/// # Safety
+/// `ptr_` must be null or point to `len` readable bytes that stay valid and
+/// unmodified for `'a`.
+pub unsafe fn borrow_bytes<'a>(ptr_: *const u8, len: usize) -> &'a [u8] {
+    if ptr_.is_null() || len == 0 { return &[]; }
+    // SAFETY: caller guarantees `ptr_` points to `len` readable bytes for the call
+    unsafe { std::slice::from_raw_parts(ptr_, len) }
+}
+
+/// Borrow a `(ptr, len)` UTF-8 string parameter without copying.
+///
+/// # Safety
+/// Same as `borrow_bytes`.
+pub unsafe fn borrow_str<'a>(ptr_: *const u8, len: usize) -> Result<&'a str, std::str::Utf8Error> {
+    std::str::from_utf8(borrow_bytes(ptr_, len))
+}
 
 /// Utility to borrow a `&str` from a NUL-terminated C string. Returns `None`
 /// if `ptr` is null or not valid UTF-8.
-pub fn c_ptr_to_str<'a>(ptr_: *const c_char) -> Option<&'a str> {
+///
+/// # Safety
+/// `ptr_` must be null or point to a NUL-terminated string that stays valid
+/// and unmodified for `'a`.
+pub unsafe fn c_ptr_to_str<'a>(ptr_: *const c_char) -> Option<&'a str> {
     if ptr_.is_null() { return None; }
     // SAFETY: caller guarantees `ptr_` points to a NUL-terminated string
     let c = unsafe { CStr::from_ptr(ptr_) };
     c.to_str().ok()
 }
+
+thread_local! {
+    static LAST_ERROR: RefCell<(i32, Option<CString>)> = const { RefCell::new((0, None)) };
+}
+
+/// Record an error for the calling thread (errno-style convention).
+/// The message stays owned by the runtime until the next error or clear.
+pub fn last_error_set(code: i32, message: &str) {
+    let cstr = CString::new(sanitize_message(message).into_owned()).expect("CString::new sanitized input");
+    LAST_ERROR.with(|e| *e.borrow_mut() = (code, Some(cstr)));
+}
+
+/// Reset the calling thread's last error to OK (code = 0, no message).
+pub fn last_error_clear() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = (0, None));
+}
+
+/// Code of the last error recorded on the calling thread, or 0 if none.
+pub fn last_error_code() -> i32 {
+    LAST_ERROR.with(|e| e.borrow().0)
+}
+
+/// Message of the last error recorded on the calling thread, or null if none.
+/// The pointer is borrowed: it is valid until the next call that sets or
+/// clears the error on this thread and must not be freed by the caller.
+pub fn last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().1.as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Convenience adapter for the errno-style convention: map a `Result<T, E>`
+/// to `Option<T>` by recording the error in the thread-local slot.
+pub fn result_to_last_error<T, E: std::fmt::Display>(result: Result<T, E>) -> Option<T> {
+    match result {
+        Ok(value) => {
+            last_error_clear();
+            Some(value)
+        }
+        Err(e) => {
+            // Default unspecified error code
+            last_error_set(-1, &e.to_string());
+            None
+        }
+    }
+}
+
+/// Error returned when a foreign caller passes a handle that does not map to a
+/// live object (never issued, already released, or released twice).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum HandleError {
+    #[error("null handle")]
+    Null,
+    #[error("unknown handle: {0:#x}")]
+    Unknown(weaveffi_handle_t),
+    #[error("stale handle (already released): {0:#x}")]
+    Stale(weaveffi_handle_t),
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Maps opaque `weaveffi_handle_t` values to Rust objects.
+///
+/// A handle encodes a slot index in its low 32 bits (offset by one so that `0`
+/// is never a valid handle) and the slot's generation in its high 32 bits.
+/// Releasing a handle bumps the generation, so a use-after-free or double-free
+/// from foreign code yields `HandleError::Stale` instead of touching memory.
+pub struct HandleMap<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Default for HandleMap<T> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> HandleMap<T> {
+    pub const fn new() -> Self { Self { slots: Vec::new(), free: Vec::new(), len: 0 } }
+
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Store `value` and return a fresh handle for it.
+    pub fn insert(&mut self, value: T) -> weaveffi_handle_t {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.value = Some(value);
+            return encode_handle(index, slot.generation);
+        }
+        let index = u32::try_from(self.slots.len()).ok().filter(|i| *i < u32::MAX).expect("HandleMap: too many slots");
+        self.slots.push(Slot { generation: 1, value: Some(value) });
+        encode_handle(index, 1)
+    }
+
+    pub fn get(&self, handle: weaveffi_handle_t) -> Result<&T, HandleError> {
+        let index = self.live_index(handle)?;
+        Ok(self.slots[index].value.as_ref().expect("live slot has a value"))
+    }
+
+    pub fn get_mut(&mut self, handle: weaveffi_handle_t) -> Result<&mut T, HandleError> {
+        let index = self.live_index(handle)?;
+        Ok(self.slots[index].value.as_mut().expect("live slot has a value"))
+    }
+
+    /// Release `handle` and return its object. The handle (and any copy of it)
+    /// is invalid afterwards; the slot is reused under a new generation, or
+    /// never again once its generation is exhausted.
+    pub fn remove(&mut self, handle: weaveffi_handle_t) -> Result<T, HandleError> {
+        let index = self.live_index(handle)?;
+        let slot = &mut self.slots[index];
+        let value = slot.value.take().expect("live slot has a value");
+        // A slot whose generation would wrap is retired, so no stale handle can alias it
+        if let Some(generation) = slot.generation.checked_add(1) {
+            slot.generation = generation;
+            self.free.push(index as u32);
+        }
+        self.len -= 1;
+        Ok(value)
+    }
+
+    fn live_index(&self, handle: weaveffi_handle_t) -> Result<usize, HandleError> {
+        if handle == 0 { return Err(HandleError::Null); }
+        let (index, generation) = decode_handle(handle).ok_or(HandleError::Unknown(handle))?;
+        let slot = self.slots.get(index as usize).ok_or(HandleError::Unknown(handle))?;
+        if slot.generation != generation || slot.value.is_none() {
+            return Err(HandleError::Stale(handle));
+        }
+        Ok(index as usize)
+    }
+}
+
+fn encode_handle(index: u32, generation: u32) -> weaveffi_handle_t {
+    (u64::from(generation) << 32) | (u64::from(index) + 1)
+}
+
+fn decode_handle(handle: weaveffi_handle_t) -> Option<(u32, u32)> {
+    let low = (handle & 0xffff_ffff) as u32;
+    let generation = (handle >> 32) as u32;
+    if low == 0 || generation == 0 { return None; }
+    Some((low - 1, generation))
+}
+
+/// Thread-safe `HandleMap`, suitable for a `static` shared by exported shims:
+///
+/// ```
+/// use weaveffi_core::abi::ConcurrentHandleMap;
+/// struct Session;
+/// static SESSIONS: ConcurrentHandleMap<Session> = ConcurrentHandleMap::new();
+/// let h = SESSIONS.insert(Session);
+/// assert!(SESSIONS.with(h, |_s| ()).is_ok());
+/// assert!(SESSIONS.remove(h).is_ok());
+/// assert!(SESSIONS.remove(h).is_err());
+/// ```
+pub struct ConcurrentHandleMap<T> {
+    inner: RwLock<HandleMap<T>>,
+}
+
+impl<T> Default for ConcurrentHandleMap<T> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> ConcurrentHandleMap<T> {
+    pub const fn new() -> Self { Self { inner: RwLock::new(HandleMap::new()) } }
+
+    pub fn len(&self) -> usize { self.read().len() }
+
+    pub fn is_empty(&self) -> bool { self.read().is_empty() }
+
+    pub fn insert(&self, value: T) -> weaveffi_handle_t { self.write().insert(value) }
+
+    /// Run `f` with a shared reference to the object behind `handle`.
+    pub fn with<R>(&self, handle: weaveffi_handle_t, f: impl FnOnce(&T) -> R) -> Result<R, HandleError> {
+        let map = self.read();
+        map.get(handle).map(f)
+    }
+
+    /// Run `f` with an exclusive reference to the object behind `handle`.
+    pub fn with_mut<R>(&self, handle: weaveffi_handle_t, f: impl FnOnce(&mut T) -> R) -> Result<R, HandleError> {
+        let mut map = self.write();
+        map.get_mut(handle).map(f)
+    }
+
+    pub fn remove(&self, handle: weaveffi_handle_t) -> Result<T, HandleError> { self.write().remove(handle) }
+
+    // A panic while holding the lock leaves the map itself consistent, so
+    // poisoning is ignored rather than turning every later call into a panic.
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HandleMap<T>> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HandleMap<T>> {
+        self.inner.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exhausted_slot_is_retired() {
+        let mut map = HandleMap::new();
+        let h = map.insert(1);
+        map.slots[0].generation = u32::MAX;
+        let h = (h & 0xffff_ffff) | (u64::from(u32::MAX) << 32);
+        assert_eq!(map.remove(h), Ok(1));
+        let fresh = map.insert(2);
+        assert_ne!(fresh & 0xffff_ffff, h & 0xffff_ffff);
+        assert_eq!(map.get(h), Err(HandleError::Stale(h)));
+    }
+
+    #[test]
+    fn interior_nul_policy() {
+        let mut err = weaveffi_error::default();
+        // SAFETY: `err` is a valid error and `stripped` a live string from this runtime
+        unsafe {
+            let stripped = string_to_c_ptr("a\0b", &mut err);
+            assert_eq!(c_ptr_to_str(stripped), Some("ab"));
+            free_string(stripped);
+            set_interior_nul(InteriorNul::Reject);
+            assert!(string_to_c_ptr("a\0b", &mut err).is_null());
+            assert_eq!(err.code, ERROR_INTERIOR_NUL);
+            error_clear(&mut err);
+            assert!(string_to_c_ptr("a\0b", ptr::null_mut()).is_null());
+        }
+        assert_eq!(last_error_code(), ERROR_INTERIOR_NUL);
+        set_interior_nul(InteriorNul::Strip);
+    }
+
+    #[test]
+    fn bytes_are_handed_over_without_copying() {
+        let data = vec![1u8, 2, 3].into_boxed_slice();
+        let original = data.as_ptr();
+        let mut len = 0;
+        // SAFETY: `len` is writable and the buffer is freed once with its length
+        unsafe {
+            let ptr = bytes_to_c_ptr(data, &mut len, ptr::null_mut());
+            assert_eq!((ptr, len), (original, 3));
+            free_bytes(ptr as *mut u8, len);
+        }
+    }
+}
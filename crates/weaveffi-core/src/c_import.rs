@@ -0,0 +1,1049 @@
+//! Import a C header as an API: the inverse of `render_c_header`.
+//!
+//! Prototypes named `weaveffi_<module>_<function>` become functions when every
+//! param and the return map onto IR types: scalars, `const uint8_t* x_ptr,
+//! size_t x_len` pairs (`bytes`; `const char*` pairs are `string`), handle
+//! typedefs, and a trailing `weaveffi_error*`. `[borrowed string]`-style markers
+//! in `@param`/`@return` docs restore what the C types leave out.
+//! `WEAVEFFI_<MODULE>_<CODE>` enum constants become the module's error codes. Doc
+//! blocks, `// Module:` markers and `WEAVEFFI_DEPRECATED` carry over.
+//!
+//! Prototypes of existing libraries, named `<prefix><function>` for a prefix
+//! mapped to a module, may also take NUL-terminated strings and leave out the
+//! `weaveffi_error*`; [`CImport::adapter`] writes the C that implements their
+//! `weaveffi_*` entry points. Every other declaration is reported with the
+//! reason it could not be mapped.
+
+use crate::templates::c_prototype;
+use heck::{ToShoutySnakeCase, ToUpperCamelCase};
+use weaveffi_ir::ir::{
+    Api, Deprecation, ErrorCode, ErrorConvention, ErrorDomain, Function, HandleType, Module, Ownership, Param, StringReturn,
+    TypeRef,
+};
+
+/// How to read a header.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Modules whose names contain `_` (otherwise `weaveffi_a_b_c` is read as
+    /// module `a`, function `b_c`), in addition to those named by `// Module:` comments
+    pub modules: Vec<String>,
+    /// `(prefix, module)`: prototypes named `<prefix><function>` are functions of
+    /// `module` that are called through an adapter, e.g. `("calc_", "calculator")`
+    pub prefixes: Vec<(String, String)>,
+}
+
+/// Result of importing a header.
+#[derive(Debug, Clone)]
+pub struct CImport {
+    pub api: Api,
+    /// Declarations that have no IR equivalent, in header order
+    pub unmapped: Vec<Unmapped>,
+    /// Functions imported through a prefix, in header order
+    adapted: Vec<Adapted>,
+    /// Whether the header declares runtime helpers such as `weaveffi_free_string`
+    has_runtime: bool,
+}
+
+/// A declaration the importer skipped.
+#[derive(Debug, Clone)]
+pub struct Unmapped {
+    /// 1-based line where the declaration starts
+    pub line: usize,
+    /// The declaration on one line
+    pub declaration: String,
+    pub reason: String,
+}
+
+/// A function imported through a prefix: its `weaveffi_*` entry point calls `symbol`.
+#[derive(Debug, Clone)]
+struct Adapted {
+    module: String,
+    function: String,
+    symbol: String,
+    /// Return type as declared
+    ret: String,
+    args: Vec<Arg>,
+}
+
+/// How an adapter passes one argument of a legacy prototype.
+#[derive(Debug, Clone)]
+enum Arg {
+    /// A scalar or handle param, as is
+    Value(String),
+    /// A `(ptr, len)` pair, the pointer cast to the declared type
+    Buffer { name: String, c_type: String },
+    /// A string param copied into a NUL-terminated buffer for the call
+    CString(String),
+    OutLen,
+    OutErr,
+}
+
+impl CImport {
+    /// C source implementing the `weaveffi_*` entry points of the functions
+    /// imported through a prefix by calling them, plus the runtime helpers when
+    /// the header declares none. It includes `header` and the `weaveffi.h`
+    /// generated from [`CImport::api`]. `None` if no prefix matched.
+    pub fn adapter(&self, header: &str) -> Option<String> {
+        if self.adapted.is_empty() {
+            return None;
+        }
+        let api = &self.api;
+        let out_param = api.error_convention == ErrorConvention::OutParam;
+        let mut out = format!(
+            "// weaveffi_* entry points for the functions in {header}, written by `weaveffi import-c`.\n\
+             // Build it into the library next to the weaveffi.h generated from the imported IDL.\n\n\
+             #include <stdlib.h>\n#include <string.h>\n#include \"{header}\"\n#include \"weaveffi.h\"\n"
+        );
+        if !self.has_runtime {
+            out.push_str(concat!(
+                "\nuint32_t weaveffi_abi_version(void) { return WEAVEFFI_ABI_VERSION; }\n",
+                "uint64_t weaveffi_api_hash(void) { return WEAVEFFI_API_HASH; }\n",
+                "\n// Strings and buffers returned by these functions are borrowed, so there is nothing to free\n",
+                "void weaveffi_error_clear(weaveffi_error* err) { if (err) { err->code = 0; err->message = NULL; } }\n",
+                "void weaveffi_free_string(const char* ptr) { (void)ptr; }\n",
+                "void weaveffi_free_bytes(uint8_t* ptr, size_t len) { (void)ptr; (void)len; }\n",
+                "bool weaveffi_set_allocator(weaveffi_malloc_fn malloc_fn, weaveffi_free_fn free_fn, void* user_data) {\n",
+                "    (void)user_data;\n",
+                "    return malloc_fn != NULL && free_fn != NULL;\n",
+                "}\n",
+            ));
+        }
+        if self.adapted.iter().any(|a| a.args.iter().any(|arg| matches!(arg, Arg::CString(_)))) {
+            out.push_str(concat!(
+                "\n// NUL-terminated copy of a string param; NULL when out of memory, reported as code -2\n",
+                "static char* weaveffi_adapter_cstr(const uint8_t* ptr, size_t len) {\n",
+                "    char* s = malloc(len + 1);\n",
+                "    if (s) {\n",
+                "        if (len) memcpy(s, ptr, len);\n",
+                "        s[len] = '\\0';\n",
+                "    }\n",
+                "    return s;\n",
+                "}\n",
+            ));
+        }
+        for a in &self.adapted {
+            let Some(module) = api.modules.iter().find(|m| m.name == a.module) else { continue };
+            let Some(f) = module.functions.iter().find(|f| f.name == a.function) else { continue };
+            let prototype = c_prototype(module, f, api);
+            out.push_str(&format!("\n{} {{\n", prototype.trim_end_matches(';')));
+            let takes_err = a.args.iter().any(|arg| matches!(arg, Arg::OutErr));
+            if out_param && !takes_err {
+                out.push_str("    if (out_err) { out_err->code = 0; out_err->message = NULL; }\n");
+            }
+            let strings: Vec<&str> = a.args.iter().filter_map(|arg| match arg {
+                Arg::CString(name) => Some(name.as_str()),
+                _ => None,
+            }).collect();
+            let fail = if a.ret == "void" { "return;" } else { "return 0;" };
+            if !strings.is_empty() {
+                for name in &strings {
+                    out.push_str(&format!("    char* {0} = weaveffi_adapter_cstr({0}_ptr, {0}_len);\n", name));
+                }
+                let missing: Vec<String> = strings.iter().map(|s| format!("!{}", s)).collect();
+                let frees: String = strings.iter().map(|s| format!("free({}); ", s)).collect();
+                // -2 is `abi::ERROR_ALLOC_FAILED`
+                let report = if out_param {
+                    format!("if (out_err) {{ out_err->code = -2; out_err->message = NULL; }} {}", fail)
+                } else {
+                    "abort();".to_string()
+                };
+                out.push_str(&format!("    if ({}) {{ {}{} }}\n", missing.join(" || "), frees, report));
+            }
+            let args: Vec<String> = a.args.iter().map(|arg| match arg {
+                Arg::Value(name) | Arg::CString(name) => name.clone(),
+                Arg::Buffer { name, c_type } => format!("({}){}_ptr, {}_len", c_type, name, name),
+                Arg::OutLen => "out_len".into(),
+                Arg::OutErr => "out_err".into(),
+            }).collect();
+            let call = format!("{}({})", a.symbol, args.join(", "));
+            let measure = f.returns == Some(TypeRef::StringUtf8) && api.string_return == StringReturn::OutLen;
+            if a.ret == "void" {
+                out.push_str(&format!("    {};\n", call));
+            } else if strings.is_empty() && !measure {
+                out.push_str(&format!("    return {};\n", call));
+                out.push_str("}\n");
+                continue;
+            } else {
+                out.push_str(&format!("    {} rv = {};\n", a.ret, call));
+            }
+            for name in &strings {
+                out.push_str(&format!("    free({});\n", name));
+            }
+            if measure {
+                out.push_str("    if (out_len) *out_len = rv ? strlen(rv) : 0;\n");
+            }
+            if a.ret != "void" {
+                out.push_str("    return rv;\n");
+            }
+            out.push_str("}\n");
+        }
+        Some(out)
+    }
+}
+
+/// Runtime helpers every generated header declares; they are not API functions.
+const RUNTIME: &[&str] = &[
+    "weaveffi_abi_version",
+    "weaveffi_api_hash",
+    "weaveffi_check_compat",
+    "weaveffi_error_clear",
+    "weaveffi_free_string",
+    "weaveffi_free_string_len",
+    "weaveffi_free_bytes",
+    "weaveffi_set_allocator",
+    "weaveffi_last_error_code",
+    "weaveffi_last_error_message",
+    "weaveffi_last_error_clear",
+];
+
+/// Words that mark a `void f(handle)` function as the handle's release function.
+const RELEASE_WORDS: &[&str] = &["free", "release", "close", "destroy", "drop", "delete", "dispose"];
+
+/// Import the prototypes in `src`.
+pub fn import_c_header(src: &str, options: &ImportOptions) -> CImport {
+    let items = scan(src);
+    let mut importer = Importer {
+        modules: Vec::new(),
+        known: options.modules.clone(),
+        prefixes: options.prefixes.clone(),
+        handles: Vec::new(),
+        unmapped: Vec::new(),
+        adapted: Vec::new(),
+        error_convention: ErrorConvention::OutParam,
+        string_return: StringReturn::NulTerminated,
+    };
+    // Longest first, so `calc_v2_` wins over `calc_`
+    importer.prefixes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    let mut has_runtime = false;
+    // Conventions and names the prototypes depend on, wherever they are declared
+    for item in &items {
+        match item {
+            Item::Module { name, .. } => importer.known.push(name.clone()),
+            Item::Decl(d) => match &d.kind {
+                Declaration::Prototype(p) if RUNTIME.contains(&p.name.as_str()) => {
+                    has_runtime = true;
+                    match p.name.as_str() {
+                        "weaveffi_last_error_code" => importer.error_convention = ErrorConvention::LastError,
+                        "weaveffi_free_string_len" => importer.string_return = StringReturn::OutLen,
+                        _ => {}
+                    }
+                }
+                Declaration::HandleTypedef(c_name) => importer.handles.push(c_name.clone()),
+                _ => {}
+            },
+            Item::ErrorCodes(_) => {}
+        }
+    }
+    for item in &items {
+        match item {
+            Item::Module { name, since, deprecated, doc } => {
+                let module = importer.module(name);
+                module.since = since.clone();
+                module.deprecated = deprecated.as_deref().map(deprecation);
+                module.doc = doc.clone();
+            }
+            Item::Decl(d @ Decl { kind: Declaration::Prototype(p), .. }) => importer.prototype(d, p),
+            _ => {}
+        }
+    }
+    // Error enums last, so their constants can be matched against every module
+    let mut error_name = None;
+    for item in &items {
+        match item {
+            Item::ErrorCodes(name) => error_name = Some(name.clone()),
+            Item::Decl(d) => {
+                if let Declaration::Enum { tag, entries } = &d.kind {
+                    importer.error_codes(d, tag.clone().or(error_name.take()), entries);
+                }
+                error_name = None;
+            }
+            Item::Module { .. } => {}
+        }
+    }
+    importer.attach_handles(&items);
+    importer.unmapped.sort_by_key(|u| u.line);
+    let Importer { modules, unmapped, adapted, error_convention, string_return, .. } = importer;
+    let api = Api {
+        version: "0.1.0".into(),
+        modules: modules.into_iter().filter(|m| !m.functions.is_empty()).collect(),
+        error_convention,
+        string_return,
+    };
+    CImport { api, unmapped, adapted, has_runtime }
+}
+
+enum Item {
+    /// `// Module: name (since x) (deprecated: note)` and the `//` lines right after it
+    Module { name: String, since: Option<String>, deprecated: Option<String>, doc: Option<String> },
+    /// `// Error codes (Name)` before an error enum
+    ErrorCodes(String),
+    Decl(Decl),
+}
+
+/// A top-level declaration with comments and preprocessor lines removed.
+struct Decl {
+    line: usize,
+    text: String,
+    kind: Declaration,
+    /// The `/** ... */` block just before it
+    doc: Option<String>,
+    /// Doc blocks inside braces, keyed by their offset in `text`
+    inner_docs: Vec<(usize, String)>,
+}
+
+/// Body of a `/** ... */` block without its `*` margins.
+fn clean_doc(body: &str) -> String {
+    let lines: Vec<&str> = body.lines().map(|l| l.trim().trim_start_matches('*').trim()).collect();
+    lines.join("\n").trim().to_string()
+}
+
+/// Split `src` into declarations. Function definitions (`static inline`),
+/// `extern "C"` blocks and preprocessor lines are skipped.
+fn scan(src: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut text = String::new();
+    let (mut line, mut start_line, mut depth) = (1, 1, 0usize);
+    let mut doc: Option<String> = None;
+    let mut inner_docs = Vec::new();
+    let mut module_doc = false;
+    let mut at_line_start = true;
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        if at_line_start && c == '#' {
+            // Preprocessor line, including `\` continuations
+            let mut end = 0;
+            for l in rest.split_inclusive('\n') {
+                end += l.len();
+                if !l.trim_end().ends_with('\\') {
+                    break;
+                }
+                line += 1;
+            }
+            rest = &rest[end.min(rest.len())..];
+            line += 1;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("/*") {
+            let end = after.find("*/").map_or(after.len(), |i| i + 2);
+            let body = &after[..end.saturating_sub(2).min(after.len())];
+            if let Some(block) = body.strip_prefix('*').filter(|_| !body.starts_with("*/")) {
+                let cleaned = clean_doc(block);
+                if depth > 0 || !text.trim().is_empty() {
+                    inner_docs.push((text.len(), cleaned));
+                } else {
+                    doc = Some(cleaned);
+                }
+            }
+            line += body.matches('\n').count();
+            rest = &after[end..];
+            text.push(' ');
+            module_doc = false;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("//") {
+            let end = after.find('\n').unwrap_or(after.len());
+            let comment = after[..end].trim();
+            rest = &after[end..];
+            if depth > 0 || !text.trim().is_empty() {
+                continue;
+            }
+            if let Some(marker) = comment.strip_prefix("Module:") {
+                let (marker, deprecated) = match marker.trim().split_once(" (deprecated: ") {
+                    Some((marker, note)) => (marker, note.strip_suffix(')').map(str::to_string)),
+                    None => (marker.trim(), None),
+                };
+                let (name, since) = match marker.split_once(" (since ") {
+                    Some((name, since)) => (name, Some(since.trim_end_matches(')').to_string())),
+                    None => (marker, None),
+                };
+                items.push(Item::Module { name: name.to_string(), since, deprecated, doc: None });
+                module_doc = true;
+            } else if let Some(name) = comment.strip_prefix("Error codes (").and_then(|c| c.strip_suffix(')')) {
+                items.push(Item::ErrorCodes(name.to_string()));
+                module_doc = false;
+            } else if module_doc {
+                if let Some(Item::Module { doc, .. }) = items.last_mut() {
+                    let doc = doc.get_or_insert_with(String::new);
+                    if !doc.is_empty() {
+                        doc.push('\n');
+                    }
+                    doc.push_str(comment);
+                }
+            }
+            continue;
+        }
+        let len = c.len_utf8();
+        if !c.is_whitespace() {
+            module_doc = false;
+            at_line_start = false;
+            if text.trim().is_empty() {
+                start_line = line;
+            }
+        }
+        match c {
+            '\n' => {
+                line += 1;
+                at_line_start = true;
+                text.push(' ');
+            }
+            '"' | '\'' => {
+                // Copy literals whole so `;` and braces inside them are not structure
+                let mut end = len;
+                let mut escaped = false;
+                for (i, ch) in rest[len..].char_indices() {
+                    if escaped {
+                        escaped = false;
+                    } else if ch == '\\' {
+                        escaped = true;
+                    } else if ch == c {
+                        end = len + i + 1;
+                        break;
+                    }
+                }
+                text.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            '{' if depth == 0 && text.trim_end().ends_with("extern \"C\"") => {
+                text.clear();
+            }
+            '{' => {
+                depth += 1;
+                text.push(c);
+            }
+            '}' if depth == 0 => {}
+            '}' => {
+                depth -= 1;
+                text.push(c);
+                let head = &text[..text.find('{').unwrap_or(0)];
+                if depth == 0 && head.contains('(') {
+                    // A function definition
+                    text.clear();
+                    doc = None;
+                    inner_docs.clear();
+                }
+            }
+            ';' if depth == 0 => {
+                let decl = std::mem::take(&mut text);
+                if !decl.trim().is_empty() {
+                    items.push(Item::Decl(Decl {
+                        line: start_line,
+                        kind: classify(&decl),
+                        text: decl,
+                        doc: doc.take(),
+                        inner_docs: std::mem::take(&mut inner_docs),
+                    }));
+                }
+            }
+            _ => text.push(c),
+        }
+        rest = &rest[len..];
+    }
+    for item in &mut items {
+        if let Item::Module { doc, .. } = item {
+            *doc = doc.take().map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+        }
+    }
+    items
+}
+
+/// One line with runs of whitespace collapsed.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+enum Declaration {
+    Prototype(Prototype),
+    /// `typedef weaveffi_handle_t weaveffi_session_t;`
+    HandleTypedef(String),
+    /// Enum tag (or typedef name) and `(constant, value)` entries
+    Enum { tag: Option<String>, entries: Vec<(String, Option<String>)> },
+    Other,
+}
+
+struct Prototype {
+    ret: String,
+    name: String,
+    /// `(type, name)`; the type is normalized, e.g. `const uint8_t*`
+    params: Vec<(String, String)>,
+    /// Message of a `WEAVEFFI_DEPRECATED("...")` prefix
+    deprecated: Option<String>,
+}
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split `const uint8_t *data` into (`const uint8_t*`, `data`); the name is empty when omitted.
+fn split_declarator(s: &str) -> (String, String) {
+    let spaced = s.replace('*', " * ");
+    let mut tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let name = match tokens.last() {
+        Some(last) if tokens.len() > 1 && is_ident(last) => tokens.pop().unwrap_or_default().to_string(),
+        _ => String::new(),
+    };
+    (tokens.join(" ").replace(" *", "*"), name)
+}
+
+/// Unescape the C string literal at the start of `s` (after whitespace), with
+/// the number of bytes it spans from there.
+fn string_literal(s: &str) -> Option<(String, usize)> {
+    let body = s.trim_start().strip_prefix('"')?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, i + 2)),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+fn classify(text: &str) -> Declaration {
+    let text = text.trim();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if text.contains('{') && words.iter().take_while(|w| !w.contains('{')).any(|w| *w == "enum") {
+        let (open, close) = (text.find('{').unwrap_or(0), text.rfind('}').unwrap_or(text.len()));
+        let head = one_line(&text[..open]);
+        let tag = head.split_whitespace().last().filter(|w| *w != "enum").map(str::to_string);
+        let tail = text[close + 1..].trim();
+        let tag = if is_ident(tail) { Some(tail.to_string()) } else { tag };
+        let entries = text[open + 1..close]
+            .split(',')
+            .map(|entry| {
+                let (name, value) = match entry.split_once('=') {
+                    Some((name, value)) => (name.trim(), Some(value.trim().to_string())),
+                    None => (entry.trim(), None),
+                };
+                (name.to_string(), value)
+            })
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        return Declaration::Enum { tag, entries };
+    }
+    if words.first() == Some(&"typedef") {
+        return match words.as_slice() {
+            ["typedef", "weaveffi_handle_t", name] if is_ident(name) => Declaration::HandleTypedef(name.to_string()),
+            _ => Declaration::Other,
+        };
+    }
+    let mut rest = text;
+    let mut deprecated = None;
+    if let Some(after) = rest.strip_prefix("WEAVEFFI_DEPRECATED") {
+        let Some((message, len)) = after.trim_start().strip_prefix('(').and_then(string_literal) else {
+            return Declaration::Other;
+        };
+        let after = after.trim_start()[1..].trim_start();
+        rest = after[len..].trim_start().trim_start_matches(')').trim_start();
+        deprecated = Some(message);
+    }
+    let rest = rest.strip_prefix("extern ").unwrap_or(rest).trim();
+    let (Some(open), Some(close)) = (rest.find('('), rest.rfind(')')) else { return Declaration::Other };
+    let (ret, name) = split_declarator(&rest[..open]);
+    if name.is_empty() || close < open {
+        return Declaration::Other;
+    }
+    let params = rest[open + 1..close].trim();
+    let params = if params.is_empty() || params == "void" {
+        Vec::new()
+    } else {
+        params.split(',').map(split_declarator).collect()
+    };
+    Declaration::Prototype(Prototype { ret, name, params, deprecated })
+}
+
+/// Element type of a `string`/`bytes` pointer.
+fn buffer(ty: &str) -> Option<TypeRef> {
+    match ty.strip_prefix("const ").unwrap_or(ty) {
+        "uint8_t*" | "unsigned char*" => Some(TypeRef::Bytes),
+        "char*" => Some(TypeRef::StringUtf8),
+        _ => None,
+    }
+}
+
+/// Parsed `/** ... */` block of a prototype.
+#[derive(Default)]
+struct DocBlock {
+    text: Option<String>,
+    /// `(name, marker, description)`
+    params: Vec<(String, Vec<String>, String)>,
+    /// Marker of `@return`
+    returns: Vec<String>,
+    since: Option<String>,
+    deprecated: Option<String>,
+}
+
+/// Words of the markers the C generator writes before `@param` and `@return` docs.
+const MARKER_WORDS: &[&str] = &["string", "borrowed", "transferred"];
+
+/// `[borrowed string] text` -> (`["borrowed", "string"]`, `text`); other
+/// bracketed text is part of the description.
+fn split_marker(desc: &str) -> (Vec<String>, &str) {
+    match desc.strip_prefix('[').and_then(|d| d.split_once(']')) {
+        Some((words, rest)) if words.split_whitespace().all(|w| MARKER_WORDS.contains(&w)) => {
+            (words.split_whitespace().map(str::to_string).collect(), rest.trim())
+        }
+        _ => (Vec::new(), desc),
+    }
+}
+
+fn marker_ownership(marker: &[String]) -> Ownership {
+    if marker.iter().any(|w| w == "borrowed") {
+        Ownership::Borrowed
+    } else if marker.iter().any(|w| w == "transferred") {
+        Ownership::Transferred
+    } else {
+        Ownership::Owned
+    }
+}
+
+fn doc_block(doc: Option<&str>) -> DocBlock {
+    let mut block = DocBlock::default();
+    let mut text = Vec::new();
+    for line in doc.unwrap_or_default().lines() {
+        if let Some(tag) = line.strip_prefix("@param ") {
+            let (name, desc) = tag.split_once(' ').unwrap_or((tag, ""));
+            let (marker, desc) = split_marker(desc.trim());
+            block.params.push((name.to_string(), marker, desc.to_string()));
+        } else if let Some((marker, desc)) = line.strip_prefix("@return").map(|r| split_marker(r.trim())).filter(|(m, _)| !m.is_empty()) {
+            block.returns = marker;
+            if !desc.is_empty() {
+                text.push(format!("@return {}", desc));
+            }
+        } else if let Some(since) = line.strip_prefix("@since ") {
+            block.since = Some(since.trim().to_string());
+        } else if let Some(note) = line.strip_prefix("@deprecated") {
+            block.deprecated = Some(note.trim().to_string());
+        } else if let Some(desc) = line.strip_prefix("@brief ") {
+            text.push(desc.to_string());
+        } else {
+            text.push(line.to_string());
+        }
+    }
+    block.text = Some(text.join("\n").trim().to_string()).filter(|t| !t.is_empty());
+    block
+}
+
+struct Importer {
+    modules: Vec<Module>,
+    /// Module names, tried longest first when splitting symbol names
+    known: Vec<String>,
+    /// `(prefix, module)`, longest prefix first
+    prefixes: Vec<(String, String)>,
+    /// C names of `weaveffi_handle_t` typedefs
+    handles: Vec<String>,
+    unmapped: Vec<Unmapped>,
+    adapted: Vec<Adapted>,
+    error_convention: ErrorConvention,
+    string_return: StringReturn,
+}
+
+impl Importer {
+    fn module(&mut self, name: &str) -> &mut Module {
+        let index = match self.modules.iter().position(|m| m.name == name) {
+            Some(i) => i,
+            None => {
+                self.modules.push(Module {
+                    name: name.to_string(),
+                    doc: None,
+                    functions: Vec::new(),
+                    errors: None,
+                    handles: Vec::new(),
+                    since: None,
+                    deprecated: None,
+                });
+                self.modules.len() - 1
+            }
+        };
+        &mut self.modules[index]
+    }
+
+    fn skip(&mut self, decl: &Decl, reason: impl Into<String>) {
+        self.unmapped.push(Unmapped { line: decl.line, declaration: one_line(&decl.text), reason: reason.into() });
+    }
+
+    /// `weaveffi_<module>_<function>` split at the longest known module name.
+    fn split_symbol<'a>(&self, symbol: &'a str) -> Option<(String, &'a str)> {
+        let rest = symbol.strip_prefix("weaveffi_")?;
+        let mut known: Vec<&String> = self.known.iter().collect();
+        known.sort_by_key(|m| std::cmp::Reverse(m.len()));
+        if let Some(m) = known.iter().find(|m| rest.strip_prefix(m.as_str()).is_some_and(|r| r.len() > 1 && r.starts_with('_'))) {
+            return Some((m.to_string(), &rest[m.len() + 1..]));
+        }
+        let (module, function) = rest.split_once('_')?;
+        (!module.is_empty() && !function.is_empty()).then(|| (module.to_string(), function))
+    }
+
+    fn scalar(&self, ty: &str) -> Option<TypeRef> {
+        Some(match ty.strip_prefix("const ").unwrap_or(ty) {
+            "int32_t" | "int" | "signed" | "signed int" => TypeRef::I32,
+            "uint32_t" | "unsigned" | "unsigned int" => TypeRef::U32,
+            "int64_t" | "long long" | "long long int" | "signed long long" => TypeRef::I64,
+            "double" => TypeRef::F64,
+            "bool" | "_Bool" => TypeRef::Bool,
+            "weaveffi_handle_t" => TypeRef::Handle,
+            other if self.handles.iter().any(|h| h == other) => TypeRef::TypedHandle(handle_name(other)),
+            _ => return None,
+        })
+    }
+
+    fn prototype(&mut self, decl: &Decl, p: &Prototype) {
+        if RUNTIME.contains(&p.name.as_str()) {
+            return;
+        }
+        let prefixed = self.prefixes.iter().find_map(|(prefix, module)| {
+            p.name.strip_prefix(prefix.as_str()).filter(|f| !f.is_empty()).map(|f| (module.clone(), f))
+        });
+        let (module, name, legacy) = match prefixed {
+            Some((module, name)) if !p.name.starts_with("weaveffi_") => (module, name, true),
+            _ => match self.split_symbol(&p.name) {
+                Some((module, name)) => (module, name, false),
+                None => {
+                    return self.skip(decl, format!("`{}` is not named `weaveffi_<module>_<function>`; map its prefix to a module to import it", p.name))
+                }
+            },
+        };
+        match self.function(decl, p, name, legacy) {
+            Ok((f, args)) => {
+                if legacy {
+                    self.adapted.push(Adapted {
+                        module: module.clone(),
+                        function: f.name.clone(),
+                        symbol: p.name.clone(),
+                        ret: p.ret.clone(),
+                        args,
+                    });
+                }
+                let module = self.module(&module);
+                let mut f = f;
+                if f.since.is_some() && f.since == module.since {
+                    f.since = None;
+                }
+                if f.deprecated.is_some() && f.deprecated == module.deprecated {
+                    f.deprecated = None;
+                }
+                module.functions.push(f);
+            }
+            Err(reason) => self.skip(decl, reason),
+        }
+    }
+
+    /// The function `p` declares, with how an adapter passes each of its
+    /// arguments. A `legacy` prototype may take NUL-terminated strings and
+    /// unnamed params and leave out `weaveffi_error*`; the buffers it returns
+    /// are borrowed.
+    fn function(&self, decl: &Decl, p: &Prototype, name: &str, legacy: bool) -> Result<(Function, Vec<Arg>), String> {
+        let mut params: Vec<Param> = Vec::new();
+        let mut args = Vec::new();
+        let (mut out_len, mut out_err) = (false, false);
+        let mut rest = p.params.iter().enumerate().peekable();
+        while let Some((index, (ty, param))) = rest.next() {
+            if ty.contains('(') || ty.contains(')') {
+                return Err("function pointer parameters have no IR equivalent".into());
+            }
+            if ty == "weaveffi_error*" {
+                if rest.peek().is_some() {
+                    return Err("`weaveffi_error*` must be the last parameter".into());
+                }
+                out_err = true;
+                args.push(Arg::OutErr);
+                continue;
+            }
+            let last = rest.peek().is_none_or(|(_, (ty, _))| ty == "weaveffi_error*");
+            if ty == "size_t*" && last {
+                out_len = true;
+                args.push(Arg::OutLen);
+                continue;
+            }
+            let param = match param.as_str() {
+                "" if legacy => format!("arg{}", index + 1),
+                "" => return Err(format!("parameter `{}` has no name", ty)),
+                name => name.to_string(),
+            };
+            if let Some(buffer) = buffer(ty) {
+                if legacy && !ty.starts_with("const ") {
+                    return Err(format!("parameter `{}`: `{}` may be written to; only `const` buffers are inputs", param, ty));
+                }
+                let base = param.strip_suffix("_ptr").unwrap_or(&param).to_string();
+                // Legacy prototypes may name the length anything
+                let paired = rest.peek().is_some_and(|(_, (len_ty, len))| {
+                    len_ty == "size_t" && (legacy || *len == format!("{}_len", base))
+                });
+                if paired {
+                    rest.next();
+                    args.push(Arg::Buffer { name: base.clone(), c_type: ty.clone() });
+                    params.push(Param { name: base, ty: buffer, doc: None, ownership: Ownership::Owned });
+                    continue;
+                }
+                if legacy && buffer == TypeRef::StringUtf8 {
+                    args.push(Arg::CString(param.clone()));
+                    params.push(Param { name: param, ty: buffer, doc: None, ownership: Ownership::Borrowed });
+                    continue;
+                }
+                return Err(match buffer {
+                    TypeRef::StringUtf8 => format!(
+                        "parameter `{}`: NUL-terminated strings are not supported; pass `const char* {1}_ptr, size_t {1}_len`",
+                        param, base
+                    ),
+                    _ => format!("parameter `{}`: `{}` needs a following `size_t {}_len`", param, ty, base),
+                });
+            }
+            let Some(ty) = self.scalar(ty) else {
+                return Err(format!("parameter `{}`: `{}` has no IR equivalent", param, ty));
+            };
+            args.push(Arg::Value(param.clone()));
+            params.push(Param { name: param, ty, doc: None, ownership: Ownership::Owned });
+        }
+        match self.error_convention {
+            ErrorConvention::OutParam if !out_err && !legacy => {
+                return Err("no trailing `weaveffi_error*` parameter to report errors through".into())
+            }
+            ErrorConvention::LastError if out_err => {
+                return Err("takes `weaveffi_error*`, but the header uses the last-error convention".into())
+            }
+            _ => {}
+        }
+        let returns = match p.ret.as_str() {
+            "void" => None,
+            ty => match buffer(ty) {
+                // The adapter measures NUL-terminated legacy results
+                Some(TypeRef::StringUtf8) if legacy && out_len => {
+                    return Err("`const char*` return with `size_t* out_len`; legacy strings must be NUL-terminated".into())
+                }
+                Some(TypeRef::StringUtf8) if !legacy && out_len != (self.string_return == StringReturn::OutLen) => {
+                    return Err(if out_len {
+                        "`const char*` return with `size_t* out_len`, but the header does not declare `weaveffi_free_string_len`".into()
+                    } else {
+                        "`const char*` return without the `size_t* out_len` the header's `string_return: out_len` requires".into()
+                    });
+                }
+                Some(TypeRef::Bytes) if !out_len => return Err(format!("`{}` return needs a `size_t* out_len` parameter", ty)),
+                Some(buffer) => Some(buffer),
+                None if out_len => return Err("`size_t* out_len` is only used with `string` or `bytes` returns".into()),
+                None => Some(self.scalar(ty).ok_or_else(|| format!("return type `{}` has no IR equivalent", ty))?),
+            },
+        };
+        let doc = doc_block(decl.doc.as_deref());
+        for (param, marker, desc) in doc.params {
+            let Some(p) = params.iter_mut().find(|p| p.name == param || format!("{}_ptr", p.name) == param) else { continue };
+            if matches!(p.ty, TypeRef::StringUtf8 | TypeRef::Bytes) {
+                if marker.iter().any(|w| w == "string") {
+                    p.ty = TypeRef::StringUtf8;
+                }
+                if !marker.is_empty() {
+                    p.ownership = marker_ownership(&marker);
+                }
+            }
+            p.doc = Some(desc).filter(|d| !d.is_empty());
+        }
+        let buffer_return = matches!(returns, Some(TypeRef::StringUtf8 | TypeRef::Bytes));
+        let return_ownership = match marker_ownership(&doc.returns) {
+            _ if !buffer_return => Ownership::Owned,
+            Ownership::Owned if legacy => Ownership::Borrowed,
+            ownership => ownership,
+        };
+        let deprecated = p.deprecated.as_deref().or(doc.deprecated.as_deref()).map(deprecation);
+        let f = Function {
+            name: name.to_string(),
+            params,
+            returns,
+            return_ownership,
+            doc: doc.text,
+            since: doc.since,
+            deprecated,
+            r#async: false,
+        };
+        Ok((f, args))
+    }
+
+    /// Error codes from an enum whose constants are `WEAVEFFI_<MODULE>_<CODE>`.
+    fn error_codes(&mut self, decl: &Decl, tag: Option<String>, entries: &[(String, Option<String>)]) {
+        let body = decl.text.find('{').unwrap_or(0);
+        let mut value = 0i64;
+        let mut offset = body;
+        for (constant, explicit) in entries {
+            // Doc blocks between the previous constant and this one describe it
+            let end = decl.text[offset..].find(constant.as_str()).map_or(offset, |i| offset + i);
+            let doc = decl.inner_docs.iter().rev().find(|(at, _)| (offset..=end).contains(at)).map(|(_, d)| d.clone());
+            offset = end + constant.len();
+            if let Some(v) = explicit {
+                match v.parse::<i64>() {
+                    Ok(v) => value = v,
+                    Err(_) => {
+                        self.skip(decl, format!("enum constant `{}`: value `{}` is not an integer literal", constant, v));
+                        continue;
+                    }
+                }
+            }
+            let code = value;
+            value += 1;
+            let mut modules: Vec<String> = self.modules.iter().map(|m| m.name.clone()).collect();
+            modules.sort_by_key(|m| std::cmp::Reverse(m.len()));
+            let owner = modules.iter().find_map(|m| {
+                let prefix = format!("WEAVEFFI_{}_", m.to_shouty_snake_case());
+                constant.strip_prefix(&prefix).filter(|c| !c.is_empty()).map(|c| (m.clone(), c.to_string()))
+            });
+            let Some((module, name)) = owner else {
+                self.skip(decl, format!("enum constant `{}` is not named `WEAVEFFI_<MODULE>_<CODE>` for an imported module", constant));
+                continue;
+            };
+            if code == 0 {
+                self.skip(decl, format!("enum constant `{}`: code 0 means success, not an error", constant));
+                continue;
+            }
+            let Ok(code) = i32::try_from(code) else {
+                self.skip(decl, format!("enum constant `{}`: code {} does not fit in `int32_t`", constant, code));
+                continue;
+            };
+            let domain = tag.as_deref().map_or_else(|| format!("{}Error", module.to_upper_camel_case()), |t| t.to_upper_camel_case());
+            let message = doc.as_deref().and_then(|d| d.lines().next()).map_or_else(|| name.to_lowercase().replace('_', " "), str::to_string);
+            let errors = self.module(&module).errors.get_or_insert_with(|| ErrorDomain { name: domain, codes: Vec::new() });
+            errors.codes.push(ErrorCode {
+                name: name.to_upper_camel_case(),
+                code,
+                doc: doc.filter(|d| *d != message),
+                message,
+            });
+        }
+    }
+
+    /// Declare each typedef'd handle in the module of its release function, or
+    /// fall back to untyped `handle` when there is none.
+    fn attach_handles(&mut self, items: &[Item]) {
+        for item in items {
+            let Item::Decl(decl @ Decl { kind: Declaration::HandleTypedef(c_name), .. }) = item else { continue };
+            let name = handle_name(c_name);
+            let ty = TypeRef::TypedHandle(name.clone());
+            let used = self.modules.iter().flat_map(|m| &m.functions).any(|f| {
+                f.returns.as_ref() == Some(&ty) || f.params.iter().any(|p| p.ty == ty)
+            });
+            if !used {
+                continue;
+            }
+            let release = self.modules.iter_mut().find_map(|m| {
+                let f = m.functions.iter().find(|f| {
+                    f.returns.is_none()
+                        && matches!(f.params.as_slice(), [p] if p.ty == ty)
+                        && f.name.split('_').any(|w| RELEASE_WORDS.contains(&w))
+                })?;
+                let release = f.name.clone();
+                Some((m, release))
+            });
+            match release {
+                Some((module, release)) => module.handles.push(HandleType { name, release: Some(release) }),
+                None => {
+                    for f in self.modules.iter_mut().flat_map(|m| m.functions.iter_mut()) {
+                        for t in f.params.iter_mut().map(|p| &mut p.ty).chain(f.returns.as_mut()) {
+                            if *t == ty {
+                                *t = TypeRef::Handle;
+                            }
+                        }
+                    }
+                    self.skip(
+                        decl,
+                        format!(
+                            "no release function for `{}` (a `void` function taking only the handle, named with free, release, close, destroy or drop); imported as untyped `handle`",
+                            c_name
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Inverse of `Deprecation::note`: "msg (deprecated since x; use y instead)".
+fn deprecation(note: &str) -> Deprecation {
+    let mut d = Deprecation::default();
+    let (message, details) = match note.strip_suffix(')').and_then(|n| n.rsplit_once(" (")) {
+        Some((message, details)) => (Some(message), details),
+        None if note.starts_with("deprecated since ") || note.starts_with("use ") => (None, note),
+        None => (Some(note), ""),
+    };
+    let mut rest = Vec::new();
+    for part in details.split("; ").filter(|p| !p.is_empty()) {
+        if let Some(since) = part.strip_prefix("deprecated since ") {
+            d.since = Some(since.to_string());
+        } else if let Some(replacement) = part.strip_prefix("use ").and_then(|p| p.strip_suffix(" instead")) {
+            d.replacement = Some(replacement.to_string());
+        } else {
+            rest.push(part);
+        }
+    }
+    if !rest.is_empty() {
+        // Parenthesized text that is not a note detail belongs to the message
+        return Deprecation { message: Some(note.to_string()), ..Default::default() };
+    }
+    d.message = message.filter(|m| !m.is_empty() && *m != "deprecated").map(str::to_string);
+    d
+}
+
+/// IR name of a handle typedef, e.g. `Session` for `weaveffi_session_t`.
+fn handle_name(c_name: &str) -> String {
+    let name = c_name.strip_prefix("weaveffi_").unwrap_or(c_name);
+    name.strip_suffix("_t").unwrap_or(name).to_upper_camel_case()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Renderer;
+    use crate::templates::{render_c_header, C_TEMPLATES};
+    use weaveffi_ir::parse::parse_api_str;
+
+    #[test]
+    fn generated_header_round_trips() {
+        let api = parse_api_str(
+            r#"
+version: "0.1.0"
+modules:
+  - name: text
+    functions:
+      - name: upper
+        params:
+          - { name: s, type: string, ownership: borrowed, doc: Text to convert }
+        return: string
+      - name: raw
+        params:
+          - { name: data, type: bytes }
+        return: bytes
+        return_ownership: transferred
+  - name: old
+    deprecated: { message: Going away, replacement: text }
+    functions:
+      - name: ping
+        params: []
+        return: i32
+"#,
+            "yaml",
+        )
+        .unwrap();
+        let renderer = Renderer::new(C_TEMPLATES, None).unwrap();
+        let header = render_c_header(&api, &renderer).unwrap();
+        let import = import_c_header(&header, &ImportOptions::default());
+        assert!(import.unmapped.is_empty());
+        assert!(import.adapter("weaveffi.h").is_none());
+        assert_eq!(render_c_header(&import.api, &renderer).unwrap(), header);
+    }
+
+    #[test]
+    fn prefixed_prototypes_are_adapted() {
+        let header = "int calc_add(int, int);\nconst char* calc_greet(const char* name);\nint calc_fill(char* buf, size_t cap);\n";
+        let options = ImportOptions { prefixes: vec![("calc_".into(), "calculator".into())], ..Default::default() };
+        let import = import_c_header(header, &options);
+        let functions = &import.api.modules[0].functions;
+        assert_eq!(functions.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["add", "greet"]);
+        assert_eq!(functions[0].params[0].name, "arg1");
+        assert_eq!(functions[1].params[0].ty, TypeRef::StringUtf8);
+        assert_eq!(functions[1].return_ownership, Ownership::Borrowed);
+        assert_eq!(import.unmapped.len(), 1);
+        let adapter = import.adapter("calc.h").unwrap();
+        assert!(adapter.contains("return calc_add(arg1, arg2);"));
+        assert!(adapter.contains("const char* rv = calc_greet(name);"));
+    }
+}
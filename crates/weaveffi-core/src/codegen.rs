@@ -1,13 +1,26 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use camino::Utf8Path;
+use rayon::prelude::*;
 use weaveffi_ir::ir::Api;
+use crate::config::GeneratorOptions;
+use crate::output::{FileSet, Output, Plan};
+use crate::render::{Bundled, Renderer};
+use crate::templates::WASM_TEMPLATES;
 use crate::wasm::write_minimal_wasm_stub;
 
-pub trait Generator {
-    fn name(&self) -> &'static str;
-    fn generate(&self, api: &Api, out_dir: &Utf8Path) -> Result<()>;
+/// `Send + Sync` so the orchestrator can run generators in parallel.
+pub trait Generator: Send + Sync {
+    /// Target name used by `--target`, `--exclude` and the `targets` config key
+    fn name(&self) -> &str;
+    /// Targets whose output this one refers to, e.g. the C header for Swift
+    fn dependencies(&self) -> Vec<&str> { Vec::new() }
+    /// Bundled templates, which a templates directory can replace one by one
+    fn templates(&self) -> Bundled { &[] }
+    /// Add the generated files to `files`, with paths relative to the output directory
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()>;
 }
 
+#[derive(Default)]
 pub struct Orchestrator<'a> {
     generators: Vec<&'a dyn Generator>,
 }
@@ -18,11 +31,89 @@ impl<'a> Orchestrator<'a> {
         self.generators.push(gen);
         self
     }
-    pub fn run(&self, api: &Api, out_dir: &Utf8Path) -> Result<()> {
-        for g in &self.generators {
-            g.generate(api, out_dir)?;
+
+    /// Keep `targets` (every registered generator when `None`) minus `exclude`,
+    /// plus the generators they depend on, in registration order.
+    pub fn select(self, targets: Option<&[String]>, exclude: &[String]) -> Result<Self> {
+        let names: Vec<&str> = self.generators.iter().copied().map(|g| g.name()).collect();
+        if let Some(name) = names.iter().enumerate().find_map(|(i, n)| names[..i].contains(n).then_some(n)) {
+            bail!("target `{}` is defined twice", name);
+        }
+        for name in targets.unwrap_or_default().iter().chain(exclude) {
+            if !names.contains(&name.as_str()) {
+                bail!("unknown target `{}`; expected one of {} or a `{}{}` plugin on PATH", name, names.join(", "), crate::plugin::PLUGIN_PREFIX, name);
+            }
+        }
+        let mut selected: Vec<&str> = match targets {
+            Some(targets) => targets.iter().map(String::as_str).filter(|t| !exclude.iter().any(|e| e == t)).collect(),
+            None => names.iter().copied().filter(|n| !exclude.iter().any(|e| e == n)).collect(),
+        };
+        let mut i = 0;
+        while i < selected.len() {
+            let generator = self.generators.iter().copied().find(|g| g.name() == selected[i]);
+            for dep in generator.map(|g| g.dependencies()).unwrap_or_default() {
+                if exclude.iter().any(|e| e == dep) {
+                    bail!("target `{}` needs `{}`, which is excluded", selected[i], dep);
+                }
+                if !names.contains(&dep) {
+                    bail!("target `{}` needs unknown target `{}`", selected[i], dep);
+                }
+                if !selected.contains(&dep) {
+                    selected.push(dep);
+                }
+            }
+            i += 1;
+        }
+        if selected.is_empty() {
+            bail!("no targets left to generate");
+        }
+        let generators = self.generators.iter().copied().filter(|g| selected.contains(&g.name())).collect();
+        Ok(Self { generators })
+    }
+
+    /// Names of the generators `run` will call, in order.
+    pub fn targets(&self) -> Vec<&str> {
+        self.generators.iter().map(|g| g.name()).collect()
+    }
+
+    /// Run every generator in parallel, keeping the files in memory. The output
+    /// is in registration order; if any generator fails, all failures are reported.
+    pub fn render(&self, api: &Api, options: &GeneratorOptions) -> Result<Output> {
+        let results: Vec<Result<FileSet>> = self
+            .generators
+            .par_iter()
+            .map(|g| {
+                let mut files = FileSet::new();
+                g.generate(api, options, &mut files)?;
+                Ok(files)
+            })
+            .collect();
+        let mut output = Output::default();
+        let mut failures = Vec::new();
+        for (g, result) in self.generators.iter().zip(results) {
+            match result {
+                // A path clash is that target's failure; keep collecting the rest
+                Ok(files) => {
+                    if let Err(e) = output.add(g.name(), files) {
+                        failures.push((g.name(), e));
+                    }
+                }
+                Err(e) => failures.push((g.name(), e)),
+            }
+        }
+        match failures.len() {
+            0 => Ok(output),
+            1 => Err(failures.remove(0).1),
+            n => {
+                let details: Vec<String> = failures.iter().map(|(name, e)| format!("  {}: {:#}", name, e)).collect();
+                Err(anyhow!("{} targets failed:\n{}", n, details.join("\n")))
+            }
         }
-        Ok(())
+    }
+
+    /// Render, then write the files that changed to `out_dir`.
+    pub fn run(&self, api: &Api, options: &GeneratorOptions, out_dir: &Utf8Path) -> Result<Plan> {
+        self.render(api, options)?.write(out_dir)
     }
 }
 
@@ -30,7 +121,36 @@ pub struct WasmGenerator;
 
 impl Generator for WasmGenerator {
     fn name(&self) -> &'static str { "wasm" }
-    fn generate(&self, _api: &Api, out_dir: &Utf8Path) -> Result<()> {
-        write_minimal_wasm_stub(out_dir)
+    fn templates(&self) -> Bundled { WASM_TEMPLATES }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
+        let renderer = Renderer::new(WASM_TEMPLATES, options.templates.as_deref())?;
+        write_minimal_wasm_stub(api, &renderer, &options.wasm.dir, files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weaveffi_ir::parse::parse_api_str;
+
+    struct Fixed(&'static str, Result<&'static str, &'static str>);
+
+    impl Generator for Fixed {
+        fn name(&self) -> &str { self.0 }
+        fn generate(&self, _: &Api, _: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
+            let path = self.1.map_err(|e| anyhow!(e))?;
+            files.add(path, "");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_failure_is_reported() {
+        let api = parse_api_str("version: \"0.1.0\"\nmodules: []\n", "yaml").unwrap();
+        let (a, b, c) = (Fixed("a", Ok("x")), Fixed("b", Ok("x")), Fixed("c", Err("broken")));
+        let orchestrator = Orchestrator::new().with_generator(&a).with_generator(&b).with_generator(&c);
+        let message = orchestrator.render(&api, &GeneratorOptions::default()).unwrap_err().to_string();
+        assert!(message.starts_with("2 targets failed"), "{}", message);
+        assert!(message.contains("both generate x") && message.contains("broken"), "{}", message);
     }
 }
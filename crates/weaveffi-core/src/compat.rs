@@ -0,0 +1,304 @@
+//! ABI compatibility: a stable fingerprint of the C surface described by an IDL,
+//! and a semver-aware diff between two versions of an IDL.
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use weaveffi_ir::ir::{Api, Function, Module, Ownership, TypeRef};
+
+/// Canonical text of everything that shapes the C ABI: symbol names, parameter
+/// and return types, buffer ownership and the API-wide conventions. Docs,
+/// error codes and `version` are deliberately left out.
+pub fn abi_signature(api: &Api) -> String {
+    let mut out = String::new();
+    writeln!(out, "errors={:?} strings={:?}", api.error_convention, api.string_return).ok();
+    for m in &api.modules {
+        for f in &m.functions {
+            let params: Vec<String> = f.params.iter().map(|p| format!("{}:{}:{:?}", p.name, p.ty, p.ownership)).collect();
+            let ret = f.returns.as_ref().map(|t| format!("{}:{:?}", t, f.return_ownership)).unwrap_or_else(|| "void".into());
+            writeln!(out, "{}.{}({})->{}", m.name, f.name, params.join(","), ret).ok();
+        }
+    }
+    out
+}
+
+/// 64-bit FNV-1a hash of [`abi_signature`]. Stable across Rust releases and
+/// platforms, so it can be embedded in generated bindings and compared at load time.
+pub fn api_hash(api: &Api) -> u64 {
+    fnv1a(abi_signature(api).as_bytes())
+}
+
+/// 64-bit FNV-1a.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+/// A single difference between two versions of an API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    ConventionChanged { setting: &'static str, old: String, new: String },
+    ModuleAdded(String),
+    ModuleRemoved(String),
+    FunctionAdded { module: String, function: String },
+    FunctionRemoved { module: String, function: String },
+    ParamAdded { module: String, function: String, param: String },
+    ParamRemoved { module: String, function: String, param: String },
+    ParamsReordered { module: String, function: String, old: Vec<String>, new: Vec<String> },
+    ParamTypeChanged { module: String, function: String, param: String, old: TypeRef, new: TypeRef },
+    ParamOwnershipChanged { module: String, function: String, param: String, old: Ownership, new: Ownership },
+    ReturnTypeChanged { module: String, function: String, old: Option<TypeRef>, new: Option<TypeRef> },
+    ReturnOwnershipChanged { module: String, function: String, old: Ownership, new: Ownership },
+    HandleAdded { module: String, name: String },
+    HandleRemoved { module: String, name: String },
+    ErrorCodeAdded { module: String, name: String, code: i32 },
+    ErrorCodeRemoved { module: String, name: String, code: i32 },
+    ErrorCodeChanged { module: String, name: String, old: i32, new: i32 },
+}
+
+impl Change {
+    /// Whether existing callers or binaries can break. Only additions are compatible.
+    pub fn is_breaking(&self) -> bool {
+        !matches!(
+            self,
+            Change::ModuleAdded(_) | Change::FunctionAdded { .. } | Change::HandleAdded { .. } | Change::ErrorCodeAdded { .. }
+        )
+    }
+}
+
+fn type_name(t: &Option<TypeRef>) -> String {
+    t.as_ref().map(ToString::to_string).unwrap_or_else(|| "void".into())
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::ConventionChanged { setting, old, new } => write!(f, "{} changed: {} -> {}", setting, old, new),
+            Change::ModuleAdded(m) => write!(f, "module added: {}", m),
+            Change::ModuleRemoved(m) => write!(f, "module removed: {}", m),
+            Change::FunctionAdded { module, function } => write!(f, "function added: {}::{}", module, function),
+            Change::FunctionRemoved { module, function } => write!(f, "function removed: {}::{}", module, function),
+            Change::ParamAdded { module, function, param } => write!(f, "param added: {}::{}({})", module, function, param),
+            Change::ParamRemoved { module, function, param } => write!(f, "param removed: {}::{}({})", module, function, param),
+            Change::ParamsReordered { module, function, old, new } => {
+                write!(f, "params reordered in {}::{}: ({}) -> ({})", module, function, old.join(", "), new.join(", "))
+            }
+            Change::ParamTypeChanged { module, function, param, old, new } => {
+                write!(f, "param type changed: {}::{}({}): {} -> {}", module, function, param, old, new)
+            }
+            Change::ParamOwnershipChanged { module, function, param, old, new } => {
+                write!(f, "param ownership changed: {}::{}({}): {:?} -> {:?}", module, function, param, old, new)
+            }
+            Change::ReturnTypeChanged { module, function, old, new } => {
+                write!(f, "return type changed: {}::{}: {} -> {}", module, function, type_name(old), type_name(new))
+            }
+            Change::ReturnOwnershipChanged { module, function, old, new } => {
+                write!(f, "return ownership changed: {}::{}: {:?} -> {:?}", module, function, old, new)
+            }
+            Change::HandleAdded { module, name } => write!(f, "handle type added: {}::{}", module, name),
+            Change::HandleRemoved { module, name } => write!(f, "handle type removed: {}::{}", module, name),
+            Change::ErrorCodeAdded { module, name, code } => write!(f, "error code added: {}::{} = {}", module, name, code),
+            Change::ErrorCodeRemoved { module, name, code } => write!(f, "error code removed: {}::{} = {}", module, name, code),
+            Change::ErrorCodeChanged { module, name, old, new } => {
+                write!(f, "error code changed: {}::{}: {} -> {}", module, name, old, new)
+            }
+        }
+    }
+}
+
+/// Minimum version bump a set of changes calls for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    None,
+    /// Additions only
+    Minor,
+    /// At least one breaking change
+    Major,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bump::None => "none",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+        })
+    }
+}
+
+/// Every change between two versions of an API, in IDL order.
+#[derive(Debug, Clone, Default)]
+pub struct AbiDiff {
+    pub changes: Vec<Change>,
+}
+
+impl AbiDiff {
+    pub fn is_breaking(&self) -> bool { self.changes.iter().any(Change::is_breaking) }
+
+    pub fn required_bump(&self) -> Bump {
+        if self.is_breaking() {
+            Bump::Major
+        } else if self.changes.is_empty() {
+            Bump::None
+        } else {
+            Bump::Minor
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VersionError {
+    #[error("invalid {which} version '{version}': {source}")]
+    Invalid { which: &'static str, version: String, source: semver::Error },
+    #[error("version went backwards: {old} -> {new}")]
+    Downgrade { old: Version, new: Version },
+    #[error("{required} version bump required, but version went {old} -> {new}")]
+    InsufficientBump { old: Version, new: Version, required: Bump },
+}
+
+/// Compare two versions of an API and classify every difference.
+pub fn diff_apis(old: &Api, new: &Api) -> AbiDiff {
+    let mut changes = Vec::new();
+    if old.error_convention != new.error_convention {
+        changes.push(Change::ConventionChanged {
+            setting: "error_convention",
+            old: format!("{:?}", old.error_convention),
+            new: format!("{:?}", new.error_convention),
+        });
+    }
+    if old.string_return != new.string_return {
+        changes.push(Change::ConventionChanged {
+            setting: "string_return",
+            old: format!("{:?}", old.string_return),
+            new: format!("{:?}", new.string_return),
+        });
+    }
+    for om in &old.modules {
+        match new.modules.iter().find(|m| m.name == om.name) {
+            Some(nm) => diff_modules(om, nm, &mut changes),
+            None => changes.push(Change::ModuleRemoved(om.name.clone())),
+        }
+    }
+    for nm in &new.modules {
+        if !old.modules.iter().any(|m| m.name == nm.name) {
+            changes.push(Change::ModuleAdded(nm.name.clone()));
+        }
+    }
+    AbiDiff { changes }
+}
+
+fn diff_modules(old: &Module, new: &Module, changes: &mut Vec<Change>) {
+    let module = &old.name;
+    for of in &old.functions {
+        match new.functions.iter().find(|f| f.name == of.name) {
+            Some(nf) => diff_functions(module, of, nf, changes),
+            None => changes.push(Change::FunctionRemoved { module: module.clone(), function: of.name.clone() }),
+        }
+    }
+    for nf in &new.functions {
+        if !old.functions.iter().any(|f| f.name == nf.name) {
+            changes.push(Change::FunctionAdded { module: module.clone(), function: nf.name.clone() });
+        }
+    }
+
+    for oh in &old.handles {
+        if !new.handles.iter().any(|h| h.name == oh.name) {
+            changes.push(Change::HandleRemoved { module: module.clone(), name: oh.name.clone() });
+        }
+    }
+    for nh in &new.handles {
+        if !old.handles.iter().any(|h| h.name == nh.name) {
+            changes.push(Change::HandleAdded { module: module.clone(), name: nh.name.clone() });
+        }
+    }
+
+    let codes = |m: &Module| -> BTreeMap<String, i32> {
+        m.errors.iter().flat_map(|d| d.codes.iter()).map(|c| (c.name.clone(), c.code)).collect()
+    };
+    let (old_codes, new_codes) = (codes(old), codes(new));
+    for (name, &code) in &old_codes {
+        match new_codes.get(name) {
+            None => changes.push(Change::ErrorCodeRemoved { module: module.clone(), name: name.clone(), code }),
+            Some(&new_code) if new_code != code => {
+                changes.push(Change::ErrorCodeChanged { module: module.clone(), name: name.clone(), old: code, new: new_code })
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, &code) in &new_codes {
+        if !old_codes.contains_key(name) {
+            changes.push(Change::ErrorCodeAdded { module: module.clone(), name: name.clone(), code });
+        }
+    }
+}
+
+fn diff_functions(module: &str, old: &Function, new: &Function, changes: &mut Vec<Change>) {
+    let (module, function) = (module.to_string(), old.name.clone());
+    let old_names: Vec<String> = old.params.iter().map(|p| p.name.clone()).collect();
+    let new_names: Vec<String> = new.params.iter().map(|p| p.name.clone()).collect();
+    let mut old_sorted = old_names.clone();
+    let mut new_sorted = new_names.clone();
+    old_sorted.sort();
+    new_sorted.sort();
+    if old_names != new_names && old_sorted == new_sorted {
+        changes.push(Change::ParamsReordered { module: module.clone(), function: function.clone(), old: old_names, new: new_names });
+    }
+
+    for op in &old.params {
+        let Some(np) = new.params.iter().find(|p| p.name == op.name) else {
+            changes.push(Change::ParamRemoved { module: module.clone(), function: function.clone(), param: op.name.clone() });
+            continue;
+        };
+        if op.ty != np.ty {
+            changes.push(Change::ParamTypeChanged {
+                module: module.clone(),
+                function: function.clone(),
+                param: op.name.clone(),
+                old: op.ty.clone(),
+                new: np.ty.clone(),
+            });
+        } else if op.ownership != np.ownership {
+            changes.push(Change::ParamOwnershipChanged {
+                module: module.clone(),
+                function: function.clone(),
+                param: op.name.clone(),
+                old: op.ownership,
+                new: np.ownership,
+            });
+        }
+    }
+    for np in &new.params {
+        if !old.params.iter().any(|p| p.name == np.name) {
+            changes.push(Change::ParamAdded { module: module.clone(), function: function.clone(), param: np.name.clone() });
+        }
+    }
+
+    if old.returns != new.returns {
+        changes.push(Change::ReturnTypeChanged { module, function, old: old.returns.clone(), new: new.returns.clone() });
+    } else if old.returns.is_some() && old.return_ownership != new.return_ownership {
+        changes.push(Change::ReturnOwnershipChanged { module, function, old: old.return_ownership, new: new.return_ownership });
+    }
+}
+
+fn parse_version(which: &'static str, version: &str) -> Result<Version, VersionError> {
+    Version::parse(version).map_err(|source| VersionError::Invalid { which, version: version.to_string(), source })
+}
+
+/// Check that `new.version` is bumped enough for `diff` under semver, treating
+/// `0.y.z` the way Cargo does: the minor number is the breaking one, and every
+/// `0.0.z` release is breaking.
+pub fn check_version_bump(old: &Api, new: &Api, diff: &AbiDiff) -> Result<(), VersionError> {
+    let old_v = parse_version("old", &old.version)?;
+    let new_v = parse_version("new", &new.version)?;
+    if new_v < old_v {
+        return Err(VersionError::Downgrade { old: old_v, new: new_v });
+    }
+    let required = diff.required_bump();
+    let ok = match required {
+        Bump::None => true,
+        Bump::Minor if old_v.major > 0 => new_v.major > old_v.major || new_v.minor > old_v.minor,
+        Bump::Minor => new_v > old_v,
+        Bump::Major if old_v.major > 0 => new_v.major > old_v.major,
+        Bump::Major if old_v.minor > 0 => new_v.major > 0 || new_v.minor > old_v.minor,
+        Bump::Major => new_v > old_v,
+    };
+    if ok { Ok(()) } else { Err(VersionError::InsufficientBump { old: old_v, new: new_v, required }) }
+}
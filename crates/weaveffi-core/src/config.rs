@@ -0,0 +1,228 @@
+//! Project configuration: `weaveffi.toml`, discovered upward from the working
+//! directory like `Cargo.toml`, with one section per generator.
+//!
+//! ```toml
+//! targets = ["c", "swift"]        # default: every generator
+//! library = "calculator"          # native library name; default "weaveffi"
+//! templates = "templates"         # replaces bundled templates file by file
+//!
+//! [swift]
+//! module = "Calculator"
+//!
+//! [android]
+//! package = "com.example.calculator"
+//!
+//! [node]
+//! package = "@example/calculator"
+//! dir = "js"
+//! ```
+//!
+//! Every key is optional; without a file, generators produce the same output as
+//! before the file existed.
+
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// File name searched for in the working directory and its ancestors.
+pub const CONFIG_FILE: &str = "weaveffi.toml";
+
+/// Options every generator receives alongside the `Api`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratorOptions {
+    /// Targets `weaveffi generate` runs when `--target` is not given; all by default
+    pub targets: Option<Vec<String>>,
+    /// Native library name the bindings load or link, without `lib` or extension
+    pub library: String,
+    pub c: COptions,
+    pub swift: SwiftOptions,
+    pub android: AndroidOptions,
+    pub node: NodeOptions,
+    pub wasm: WasmOptions,
+    /// Directory of templates replacing bundled ones; see `crate::render`
+    pub templates: Option<Utf8PathBuf>,
+    /// External generators, by target name; see `crate::plugin`
+    pub plugins: BTreeMap<String, PluginOptions>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct COptions {
+    /// Output subdirectory
+    pub dir: Utf8PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwiftOptions {
+    pub dir: Utf8PathBuf,
+    /// Swift package, product and module name
+    pub module: String,
+    /// Overrides the top-level `library`
+    pub library: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AndroidOptions {
+    pub dir: Utf8PathBuf,
+    /// Gradle namespace and Kotlin package
+    pub package: String,
+    /// Gradle `rootProject.name`
+    pub project: String,
+    /// Overrides the top-level `library`
+    pub library: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeOptions {
+    pub dir: Utf8PathBuf,
+    /// npm package name
+    pub package: String,
+    /// npm package version; defaults to the IDL `version`
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasmOptions {
+    pub dir: Utf8PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PluginOptions {
+    /// Executable to run; `weaveffi-gen-<name>` on `PATH` when omitted.
+    /// Relative paths are resolved against the config file's directory
+    pub command: Option<PathBuf>,
+    pub args: Vec<String>,
+    /// Output subdirectory; defaults to the target name
+    pub dir: Option<Utf8PathBuf>,
+    /// Targets the plugin's output refers to, e.g. `["c"]`
+    pub dependencies: Vec<String>,
+    /// Passed through to the plugin as `options`
+    pub options: toml::Table,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            targets: None,
+            library: "weaveffi".into(),
+            c: COptions::default(),
+            swift: SwiftOptions::default(),
+            android: AndroidOptions::default(),
+            node: NodeOptions::default(),
+            wasm: WasmOptions::default(),
+            templates: None,
+            plugins: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for COptions {
+    fn default() -> Self { Self { dir: "c".into() } }
+}
+
+impl Default for SwiftOptions {
+    fn default() -> Self { Self { dir: "swift".into(), module: "WeaveFFI".into(), library: None } }
+}
+
+impl Default for AndroidOptions {
+    fn default() -> Self {
+        Self { dir: "android".into(), package: "com.weaveffi".into(), project: "weaveffi".into(), library: None }
+    }
+}
+
+impl Default for NodeOptions {
+    fn default() -> Self { Self { dir: "node".into(), package: "weaveffi".into(), version: None } }
+}
+
+impl Default for WasmOptions {
+    fn default() -> Self { Self { dir: "wasm".into() } }
+}
+
+impl GeneratorOptions {
+    /// Parse and check the contents of a config file.
+    pub fn from_toml(src: &str) -> Result<Self> {
+        let options: Self = toml::from_str(src)?;
+        options.check()?;
+        Ok(options)
+    }
+
+    /// Load the nearest `weaveffi.toml` at or above `start`, returning its path.
+    /// A `weaveffi.toml` that is an IDL file (it has `modules`) is skipped.
+    pub fn discover(start: &Path) -> Result<(Self, Option<PathBuf>)> {
+        for dir in start.ancestors() {
+            let path = dir.join(CONFIG_FILE);
+            if !path.is_file() {
+                continue;
+            }
+            let src = std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+            let is_idl = src.parse::<toml::Table>().is_ok_and(|t| t.contains_key("modules"));
+            if is_idl {
+                continue;
+            }
+            let mut options = Self::from_toml(&src).with_context(|| format!("invalid config file {}", path.display()))?;
+            if let Some(templates) = options.templates.as_mut().filter(|t| t.is_relative()) {
+                *templates = Utf8PathBuf::from_path_buf(dir.join(&*templates))
+                    .map_err(|p| anyhow::anyhow!("templates path {} is not UTF-8", p.display()))?;
+            }
+            for plugin in options.plugins.values_mut() {
+                // A bare command name is looked up on PATH when run
+                if let Some(command) = plugin.command.as_mut().filter(|c| c.is_relative() && c.components().count() > 1) {
+                    *command = dir.join(command.strip_prefix(".").unwrap_or(command));
+                }
+            }
+            return Ok((options, Some(path)));
+        }
+        Ok((Self::default(), None))
+    }
+
+    pub fn swift_library(&self) -> &str { self.swift.library.as_deref().unwrap_or(&self.library) }
+
+    pub fn android_library(&self) -> &str { self.android.library.as_deref().unwrap_or(&self.library) }
+
+    fn check(&self) -> Result<()> {
+        let dirs = [("c", &self.c.dir), ("swift", &self.swift.dir), ("android", &self.android.dir), ("node", &self.node.dir), ("wasm", &self.wasm.dir)];
+        let plugin_dirs = self.plugins.iter().filter_map(|(name, p)| p.dir.as_ref().map(|d| (format!("plugins.{}", name), d)));
+        for (section, dir) in dirs.into_iter().map(|(s, d)| (s.to_string(), d)).chain(plugin_dirs) {
+            let nested = dir.components().all(|c| matches!(c, camino::Utf8Component::Normal(_)));
+            if dir.as_str().is_empty() || !nested {
+                bail!("[{}] dir must be a relative path inside the output directory, got `{}`", section, dir);
+            }
+        }
+        let is_ident = |s: &str| s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !self.android.package.split('.').all(is_ident) {
+            bail!("[android] package must be a dotted Java package name, got `{}`", self.android.package);
+        }
+        if !is_ident(&self.swift.module) {
+            bail!("[swift] module must be a Swift identifier, got `{}`", self.swift.module);
+        }
+        for name in self.plugins.keys() {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                bail!("plugin name `{}` may only contain letters, digits, `-` and `_`", name);
+            }
+        }
+        for library in [Some(&self.library), self.swift.library.as_ref(), self.android.library.as_ref()].into_iter().flatten() {
+            if library.is_empty() || library.contains(['/', '\\', ' ']) {
+                bail!("library must be a bare library name such as `calculator`, got `{}`", library);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path from output subdirectory `from` to `to` (both relative to the output
+/// root), e.g. `../../../../c` from `android/src/main/cpp` to `c`.
+pub fn relative_path(from: &Utf8Path, to: &Utf8Path) -> Utf8PathBuf {
+    let mut path = Utf8PathBuf::new();
+    for _ in from.components() {
+        path.push("..");
+    }
+    path.join(to)
+}
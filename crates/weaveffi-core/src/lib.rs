@@ -2,6 +2,13 @@
 
 pub mod validate;
 pub mod codegen;
+pub mod config;
 pub mod abi;
+pub mod compat;
 pub mod templates;
+pub mod render;
+pub mod output;
 pub mod wasm;
+pub mod verify;
+pub mod plugin;
+pub mod c_import;
@@ -0,0 +1,240 @@
+//! In-memory generator output and incremental writes.
+//!
+//! Generators add their files to a [`FileSet`] instead of writing them. The
+//! combined [`Output`] is compared with the output directory so only files
+//! whose contents changed are written, which keeps Xcode, Gradle and tsc
+//! caches warm. `.weaveffi-manifest.json` records the hash of every generated
+//! file: a later run deletes files that are no longer generated and warns
+//! about files that were edited by hand.
+
+use crate::compat::fnv1a;
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Name of the manifest in the output directory.
+pub const MANIFEST_FILE: &str = ".weaveffi-manifest.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// Files produced by one generator, keyed by path relative to the output directory.
+#[derive(Debug, Clone, Default)]
+pub struct FileSet {
+    files: BTreeMap<Utf8PathBuf, String>,
+}
+
+impl FileSet {
+    pub fn new() -> Self { Self::default() }
+
+    /// Add the file at `path`, replacing any earlier contents.
+    pub fn add(&mut self, path: impl Into<Utf8PathBuf>, contents: impl Into<String>) {
+        self.files.insert(path.into(), contents.into());
+    }
+
+    pub fn get(&self, path: &Utf8Path) -> Option<&str> {
+        self.files.get(path).map(String::as_str)
+    }
+
+    /// Files in path order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Utf8Path, &str)> {
+        self.files.iter().map(|(p, c)| (p.as_path(), c.as_str()))
+    }
+
+    pub fn len(&self) -> usize { self.files.len() }
+    pub fn is_empty(&self) -> bool { self.files.is_empty() }
+}
+
+/// Contents of `.weaveffi-manifest.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Every generated file, by path relative to the output directory
+    pub files: BTreeMap<Utf8PathBuf, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Target that generated the file
+    pub target: String,
+    /// FNV-1a hash of the contents as written, in hex
+    pub hash: String,
+}
+
+impl Manifest {
+    /// The manifest in `out_dir`, if there is one.
+    pub fn load(out_dir: &Utf8Path) -> Result<Option<Self>> {
+        let path = out_dir.join(MANIFEST_FILE);
+        let Some(src) = read_existing(&path)? else { return Ok(None) };
+        let manifest: Self = serde_json::from_slice(&src)
+            .with_context(|| format!("invalid {}; delete it to regenerate every file", path))?;
+        if manifest.version != MANIFEST_VERSION {
+            bail!("{} has version {}, but this weaveffi writes version {}; delete it to regenerate every file", path, manifest.version, MANIFEST_VERSION);
+        }
+        Ok(Some(manifest))
+    }
+}
+
+/// Everything the selected generators produced, by target in run order.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    targets: Vec<(String, FileSet)>,
+}
+
+impl Output {
+    /// Add the files of `target`; two targets may not produce the same path.
+    pub fn add(&mut self, target: &str, files: FileSet) -> Result<()> {
+        for (other, existing) in &self.targets {
+            if let Some((path, _)) = files.iter().find(|(p, _)| existing.get(p).is_some()) {
+                bail!("targets `{}` and `{}` both generate {}", other, target, path);
+            }
+        }
+        self.targets.push((target.to_string(), files));
+        Ok(())
+    }
+
+    /// Compare with `out_dir` and the manifest there without touching the disk.
+    /// Only files previously generated by one of the targets in this output can
+    /// be deleted; files of other targets stay in the manifest as they are.
+    pub fn plan(&self, out_dir: &Utf8Path) -> Result<Plan> {
+        let old = Manifest::load(out_dir)?.unwrap_or_default();
+        let ran = |target: &str| self.targets.iter().any(|(t, _)| t == target);
+        let mut manifest = Manifest {
+            version: MANIFEST_VERSION,
+            files: old.files.iter().filter(|(_, e)| !ran(&e.target)).map(|(p, e)| (p.clone(), e.clone())).collect(),
+        };
+        let mut files = Vec::new();
+        let mut warnings = Vec::new();
+        for (target, set) in &self.targets {
+            for (path, contents) in set.iter() {
+                let full = out_dir.join(path);
+                let old_contents = read_existing(&full)?;
+                let change = match &old_contents {
+                    None => FileChange::Create,
+                    Some(c) if c == contents.as_bytes() => FileChange::Unchanged,
+                    Some(c) => {
+                        if old.files.get(path).is_some_and(|e| e.hash != content_hash(c)) {
+                            warnings.push(format!("{} was edited by hand since it was generated", full));
+                        }
+                        FileChange::Update
+                    }
+                };
+                manifest.files.insert(path.to_path_buf(), ManifestEntry { target: target.clone(), hash: content_hash(contents.as_bytes()) });
+                files.push(PlannedFile {
+                    path: path.to_path_buf(),
+                    change,
+                    contents: Some(contents.to_string()),
+                    old_contents: old_contents.map(|c| String::from_utf8_lossy(&c).into_owned()),
+                });
+            }
+        }
+        for (path, entry) in &old.files {
+            if !ran(&entry.target) || manifest.files.contains_key(path) {
+                continue;
+            }
+            let full = out_dir.join(path);
+            match read_existing(&full)? {
+                None => {}
+                Some(c) if content_hash(&c) == entry.hash => files.push(PlannedFile {
+                    path: path.clone(),
+                    change: FileChange::Delete,
+                    contents: None,
+                    old_contents: Some(String::from_utf8_lossy(&c).into_owned()),
+                }),
+                Some(_) => warnings.push(format!("{} is no longer generated but was edited by hand, so it is kept", full)),
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Plan { files, warnings, manifest })
+    }
+
+    /// Write the files that changed, delete stale ones and update the manifest.
+    pub fn write(&self, out_dir: &Utf8Path) -> Result<Plan> {
+        let plan = self.plan(out_dir)?;
+        plan.apply(out_dir)?;
+        Ok(plan)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Create,
+    Update,
+    Delete,
+    Unchanged,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    /// Relative to the output directory
+    pub path: Utf8PathBuf,
+    pub change: FileChange,
+    /// New contents; `None` when deleted
+    pub contents: Option<String>,
+    /// Contents on disk; `None` when created
+    pub old_contents: Option<String>,
+}
+
+/// What writing an [`Output`] does to an output directory.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    /// Generated and stale files in path order
+    pub files: Vec<PlannedFile>,
+    /// Hand-edited files that are overwritten or left in place
+    pub warnings: Vec<String>,
+    manifest: Manifest,
+}
+
+impl Plan {
+    /// Files that are created, updated or deleted.
+    pub fn changes(&self) -> impl Iterator<Item = &PlannedFile> {
+        self.files.iter().filter(|f| f.change != FileChange::Unchanged)
+    }
+
+    pub fn count(&self, change: FileChange) -> usize {
+        self.files.iter().filter(|f| f.change == change).count()
+    }
+
+    pub fn apply(&self, out_dir: &Utf8Path) -> Result<()> {
+        std::fs::create_dir_all(out_dir).with_context(|| format!("failed to create output directory: {}", out_dir))?;
+        for file in self.changes() {
+            let path = out_dir.join(&file.path);
+            match &file.contents {
+                Some(contents) => {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&path, contents).with_context(|| format!("failed to write {}", path))?;
+                }
+                None => {
+                    std::fs::remove_file(&path).with_context(|| format!("failed to delete {}", path))?;
+                    // Drop directories the deletion left empty, e.g. a removed module's
+                    for dir in path.ancestors().skip(1).take_while(|d| d.starts_with(out_dir) && *d != out_dir) {
+                        if std::fs::remove_dir(dir).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let path = out_dir.join(MANIFEST_FILE);
+        let json = serde_json::to_string_pretty(&self.manifest)? + "\n";
+        if read_existing(&path)?.as_deref() != Some(json.as_bytes()) {
+            std::fs::write(&path, json).with_context(|| format!("failed to write {}", path))?;
+        }
+        Ok(())
+    }
+}
+
+fn content_hash(contents: &[u8]) -> String {
+    format!("{:016x}", fnv1a(contents))
+}
+
+/// Contents of `path`, or `None` if it does not exist.
+fn read_existing(path: &Utf8Path) -> Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path)),
+    }
+}
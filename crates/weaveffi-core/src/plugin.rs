@@ -0,0 +1,165 @@
+//! Out-of-process generators: `weaveffi-gen-<name>` executables that speak a
+//! JSON protocol over stdin and stdout.
+//!
+//! The plugin reads one `PluginRequest` from stdin and writes one
+//! `PluginResponse` to stdout, then exits with status 0. Anything it prints to
+//! stderr is shown to the user. Returned paths are relative to the plugin's
+//! output directory and may not leave it.
+
+use crate::codegen::Generator;
+use crate::config::{GeneratorOptions, PluginOptions};
+use crate::output::FileSet;
+use anyhow::{bail, Context, Result};
+use camino::{Utf8Component, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use weaveffi_ir::ir::Api;
+
+/// Protocol version sent in every request; a plugin must answer with the same one.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Prefix of plugin executables found on `PATH`.
+pub const PLUGIN_PREFIX: &str = "weaveffi-gen-";
+
+/// Sent to the plugin on stdin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginRequest {
+    pub protocol: u32,
+    /// Target name the plugin was invoked as
+    pub target: String,
+    /// The validated API
+    pub api: Api,
+    /// Native library name from `weaveffi.toml`
+    pub library: String,
+    /// The plugin's `options` table from `weaveffi.toml`, or `{}`
+    pub options: serde_json::Value,
+}
+
+/// Read from the plugin's stdout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginResponse {
+    pub protocol: u32,
+    pub files: Vec<PluginFile>,
+    /// Shown to the user; generation still succeeds
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginFile {
+    /// Relative to the plugin's output directory
+    pub path: Utf8PathBuf,
+    pub contents: String,
+}
+
+/// A generator backed by an external executable.
+#[derive(Debug, Clone)]
+pub struct PluginGenerator {
+    name: String,
+    /// `None` until run: found on `PATH` then
+    command: Option<PathBuf>,
+    args: Vec<String>,
+    dir: Utf8PathBuf,
+    dependencies: Vec<String>,
+    options: serde_json::Value,
+}
+
+impl PluginGenerator {
+    /// A plugin declared under `[plugins.<name>]`; without a `command`, it is
+    /// looked up on `PATH` as `weaveffi-gen-<name>`.
+    pub fn configured(name: &str, config: &PluginOptions) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            command: config.command.clone(),
+            args: config.args.clone(),
+            dir: config.dir.clone().unwrap_or_else(|| name.into()),
+            dependencies: config.dependencies.clone(),
+            options: serde_json::to_value(&config.options)?,
+        })
+    }
+
+    /// `weaveffi-gen-<name>` from `PATH` with default settings, if there is one.
+    pub fn from_path(name: &str) -> Option<Self> {
+        let command = find_plugin(name)?;
+        Self::configured(name, &PluginOptions { command: Some(command), ..Default::default() }).ok()
+    }
+
+    fn invoke(&self, request: &PluginRequest) -> Result<PluginResponse> {
+        let command = match &self.command {
+            Some(command) => command.clone(),
+            None => find_plugin(&self.name).with_context(|| format!("`{}{}` not found on PATH", PLUGIN_PREFIX, self.name))?,
+        };
+        let mut child = Command::new(&command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("failed to start {}", command.display()))?;
+        let input = serde_json::to_vec(request)?;
+        let mut stdin = child.stdin.take().context("plugin stdin unavailable")?;
+        // Write from another thread so a plugin that answers early cannot deadlock us
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // A plugin may exit without reading its input; its status says whether that was a failure
+        let written = writer.join().map_err(|_| anyhow::anyhow!("plugin stdin writer panicked"))?;
+        if !output.status.success() {
+            bail!("{} exited with {}", command.display(), output.status);
+        }
+        if let Err(e) = written {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(e).context("failed to send the request");
+            }
+        }
+        let response: PluginResponse = serde_json::from_slice(&output.stdout).context("invalid response on stdout")?;
+        if response.protocol != PROTOCOL_VERSION {
+            bail!("plugin speaks protocol version {}, but this weaveffi speaks version {}", response.protocol, PROTOCOL_VERSION);
+        }
+        Ok(response)
+    }
+}
+
+impl Generator for PluginGenerator {
+    fn name(&self) -> &str { &self.name }
+    fn dependencies(&self) -> Vec<&str> { self.dependencies.iter().map(String::as_str).collect() }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
+        let request = PluginRequest {
+            protocol: PROTOCOL_VERSION,
+            target: self.name.clone(),
+            api: api.clone(),
+            library: options.library.clone(),
+            options: self.options.clone(),
+        };
+        let response = self.invoke(&request).with_context(|| format!("plugin `{}` failed", self.name))?;
+        for warning in &response.warnings {
+            eprintln!("warning: plugin `{}`: {}", self.name, warning);
+        }
+        for file in response.files {
+            let nested = file.path.components().all(|c| matches!(c, Utf8Component::Normal(_)));
+            if file.path.as_str().is_empty() || !nested {
+                bail!("plugin `{}` returned path `{}`, which is not inside its output directory", self.name, file.path);
+            }
+            files.add(self.dir.join(&file.path), file.contents);
+        }
+        Ok(())
+    }
+}
+
+/// Full path of `weaveffi-gen-<name>` on `PATH`.
+pub fn find_plugin(name: &str) -> Option<PathBuf> {
+    let file = format!("{}{}{}", PLUGIN_PREFIX, name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?).map(|dir| dir.join(&file)).find(|p| is_executable(p))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
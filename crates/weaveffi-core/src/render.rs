@@ -0,0 +1,67 @@
+//! Tera rendering for generator outputs.
+//!
+//! Every generator bundles its templates under a name such as
+//! `c/weaveffi.h.tera` and renders them with a typed context built from the
+//! `Api`. A file at the same relative path in the templates directory
+//! (`--templates` or the `templates` config key) replaces the bundled one.
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8Path;
+use serde::Serialize;
+use tera::{Context, Tera};
+
+/// Bundled templates as `(name, source)` pairs.
+pub type Bundled = &'static [(&'static str, &'static str)];
+
+/// Suffix of every template name.
+pub const TEMPLATE_SUFFIX: &str = ".tera";
+
+pub struct Renderer {
+    tera: Tera,
+}
+
+impl Renderer {
+    /// `bundled`, with each template replaced by `<overrides>/<name>` when that file exists.
+    pub fn new(bundled: Bundled, overrides: Option<&Utf8Path>) -> Result<Self> {
+        let mut templates = Vec::new();
+        for &(name, src) in bundled {
+            let path = overrides.map(|dir| dir.join(name)).filter(|p| p.is_file());
+            let src = match path {
+                Some(path) => std::fs::read_to_string(&path).with_context(|| format!("failed to read template {}", path))?,
+                None => src.to_string(),
+            };
+            templates.push((name, src));
+        }
+        let mut tera = Tera::default();
+        // Output is source code, not HTML
+        tera.autoescape_on(Vec::new());
+        tera.add_raw_templates(templates).context("invalid template")?;
+        Ok(Self { tera })
+    }
+
+    pub fn render(&self, name: &str, context: &impl Serialize) -> Result<String> {
+        let context = Context::from_serialize(context)?;
+        self.tera.render(name, &context).with_context(|| format!("failed to render template `{}`", name))
+    }
+}
+
+/// Error if a `.tera` file under `dir` would not replace any of `known`, which
+/// is most likely a typo in its name.
+pub fn check_overrides(dir: &Utf8Path, known: &[&str]) -> Result<()> {
+    if !dir.is_dir() {
+        bail!("templates directory {} does not exist", dir);
+    }
+    for entry in walkdir::WalkDir::new(dir) {
+        let entry = entry?;
+        let Some(path) = Utf8Path::from_path(entry.path()) else { continue };
+        if !entry.file_type().is_file() || !path.as_str().ends_with(TEMPLATE_SUFFIX) {
+            continue;
+        }
+        // Template names always use `/`
+        let name = path.strip_prefix(dir)?.components().map(|c| c.as_str()).collect::<Vec<_>>().join("/");
+        if !known.contains(&name.as_str()) {
+            bail!("template {} does not replace a bundled template; expected one of {}", path, known.join(", "));
+        }
+    }
+    Ok(())
+}
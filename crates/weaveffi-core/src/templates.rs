@@ -1,4 +1,10 @@
-use weaveffi_ir::ir::{Api, Module, Param, TypeRef};
+use crate::abi::ABI_VERSION;
+use crate::compat::api_hash;
+use crate::render::{Bundled, Renderer};
+use anyhow::Result;
+use serde::Serialize;
+use heck::{ToShoutySnakeCase, ToSnakeCase};
+use weaveffi_ir::ir::{Api, Deprecation, ErrorConvention, Function, HandleType, Module, Ownership, Param, StringReturn, TypeRef};
 
 fn c_type_for_param(ty: &TypeRef, name: &str) -> String {
     match ty {
@@ -10,22 +16,105 @@ fn c_type_for_param(ty: &TypeRef, name: &str) -> String {
         TypeRef::StringUtf8 => format!("const uint8_t* {}_ptr, size_t {}_len", name, name),
         TypeRef::Bytes => format!("const uint8_t* {}_ptr, size_t {}_len", name, name),
         TypeRef::Handle => format!("weaveffi_handle_t {}", name),
+        TypeRef::TypedHandle(h) => format!("{} {}", c_handle_type_name(h), name),
     }
 }
 
-fn c_ret_type_for(ty: &TypeRef) -> (&'static str, bool) {
+fn c_ret_type_for(ty: &TypeRef, string_return: StringReturn) -> (String, bool) {
     match ty {
-        TypeRef::I32 => ("int32_t", false),
-        TypeRef::U32 => ("uint32_t", false),
-        TypeRef::I64 => ("int64_t", false),
-        TypeRef::F64 => ("double", false),
-        TypeRef::Bool => ("bool", false),
-        TypeRef::StringUtf8 => ("const char*", false),
-        TypeRef::Bytes => ("const uint8_t*", true), // requires out_len param
-        TypeRef::Handle => ("weaveffi_handle_t", false),
+        TypeRef::StringUtf8 if string_return == StringReturn::OutLen => ("const char*".into(), true),
+        TypeRef::I32 => ("int32_t".into(), false),
+        TypeRef::U32 => ("uint32_t".into(), false),
+        TypeRef::I64 => ("int64_t".into(), false),
+        TypeRef::F64 => ("double".into(), false),
+        TypeRef::Bool => ("bool".into(), false),
+        TypeRef::StringUtf8 => ("const char*".into(), false),
+        TypeRef::Bytes => ("const uint8_t*".into(), true), // requires out_len param
+        TypeRef::Handle => ("weaveffi_handle_t".into(), false),
+        TypeRef::TypedHandle(h) => (c_handle_type_name(h), false),
     }
 }
 
+/// C typedef for a typed handle, e.g. `Session` -> `weaveffi_session_t`.
+fn c_handle_type_name(handle: &str) -> String {
+    format!("weaveffi_{}_t", handle.to_snake_case())
+}
+
+fn declared_handles(api: &Api) -> impl Iterator<Item = &HandleType> {
+    api.modules.iter().flat_map(|m| m.handles.iter())
+}
+
+/// Deprecation in effect for `f`: its own, else its module's.
+pub fn effective_deprecation<'a>(module: &'a Module, f: &'a Function) -> Option<&'a Deprecation> {
+    f.deprecated.as_ref().or(module.deprecated.as_ref())
+}
+
+/// Escape text for a double-quoted C, Swift or Kotlin string literal.
+pub fn escape_literal(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Lines of an optional doc string, ready to prefix with a comment marker.
+pub fn doc_lines(doc: Option<&str>) -> Vec<&str> {
+    doc.map(|d| d.trim().lines().map(str::trim_end).collect()).unwrap_or_default()
+}
+
+/// `/** ... */` block (Doxygen, KDoc, TSDoc); empty when there is nothing to say.
+pub fn block_comment(indent: &str, lines: &[String]) -> String {
+    if lines.is_empty() { return String::new(); }
+    let mut out = format!("{}/**\n", indent);
+    for line in lines {
+        let line = line.replace("*/", "*\\/");
+        if line.is_empty() { out.push_str(&format!("{} *\n", indent)); } else { out.push_str(&format!("{} * {}\n", indent, line)); }
+    }
+    out.push_str(&format!("{} */\n", indent));
+    out
+}
+
+/// Body of a function's doc block: its `doc`, then one `param_tag` per documented
+/// param, `@since` and, if `deprecated_tag`, `@deprecated`.
+pub fn function_doc_lines(module: &Module, f: &Function, param_tag: fn(&str, &str) -> String, deprecated_tag: bool) -> Vec<String> {
+    let mut lines: Vec<String> = doc_lines(f.doc.as_deref()).into_iter().map(String::from).collect();
+    let mut tags: Vec<String> = Vec::new();
+    for p in &f.params {
+        let doc = doc_lines(p.doc.as_deref());
+        if !doc.is_empty() { tags.push(param_tag(&p.name, &doc.join(" "))); }
+    }
+    if let Some(since) = f.since.as_ref().or(module.since.as_ref()) {
+        tags.push(format!("@since {}", since));
+    }
+    if let Some(d) = effective_deprecation(module, f).filter(|_| deprecated_tag) {
+        tags.push(format!("@deprecated {}", d.note()));
+    }
+    if !lines.is_empty() && !tags.is_empty() { lines.push(String::new()); }
+    lines.extend(tags);
+    lines
+}
+
+/// Doxygen body of a C prototype. The C types do not say whether a `(ptr, len)`
+/// pair is text or who owns a buffer, so params and returns that differ from an
+/// owned `bytes` get a marker such as `[borrowed string]`, which `c_import` reads back.
+fn c_doc_lines(module: &Module, f: &Function) -> Vec<String> {
+    let mut f = f.clone();
+    for p in &mut f.params {
+        let mut words = Vec::new();
+        if p.ownership != Ownership::Owned { words.push(p.ownership.as_str()); }
+        if p.ty == TypeRef::StringUtf8 { words.push("string"); }
+        if words.is_empty() { continue; }
+        let marker = format!("[{}]", words.join(" "));
+        p.doc = Some(match p.doc.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+            Some(doc) => format!("{} {}", marker, doc),
+            None => marker,
+        });
+    }
+    let mut lines = function_doc_lines(module, &f, |p, d| format!("@param {} {}", p, d), true);
+    if f.return_ownership != Ownership::Owned {
+        if lines.last().is_some_and(|l| !l.starts_with('@')) { lines.push(String::new()); }
+        lines.push(format!("@return [{}]", f.return_ownership.as_str()));
+    }
+    lines
+}
+
 fn c_symbol_name(module: &str, func: &str) -> String {
     format!("weaveffi_{}_{}", module, func)
 }
@@ -37,93 +126,173 @@ fn c_params_sig(params: &[Param]) -> Vec<String> {
         .collect()
 }
 
-pub fn render_c_header(api: &Api) -> String {
-    let mut out = String::new();
-    out.push_str("#ifndef WEAVEFFI_H\n");
-    out.push_str("#define WEAVEFFI_H\n\n");
-    out.push_str("#include <stdint.h>\n");
-    out.push_str("#include <stddef.h>\n");
-    out.push_str("#include <stdbool.h>\n\n");
-    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
-
-    out.push_str("typedef uint64_t weaveffi_handle_t;\n\n");
-    out.push_str("typedef struct weaveffi_error { int32_t code; const char* message; } weaveffi_error;\n\n");
-    out.push_str("void weaveffi_error_clear(weaveffi_error* err);\n");
-    out.push_str("void weaveffi_free_string(const char* ptr);\n");
-    out.push_str("void weaveffi_free_bytes(uint8_t* ptr, size_t len);\n\n");
-
-    for m in &api.modules {
-        render_module_header(&mut out, m);
-    }
+/// Templates of the C generator.
+pub const C_TEMPLATES: Bundled = &[
+    ("c/weaveffi.h.tera", include_str!("../templates/c/weaveffi.h.tera")),
+    ("c/weaveffi.c.tera", include_str!("../templates/c/weaveffi.c.tera")),
+];
 
-    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n");
-    out.push_str("#endif // WEAVEFFI_H\n");
-    out
+/// Context of `c/weaveffi.h.tera` and `c/weaveffi.c.tera`.
+#[derive(Serialize)]
+struct CHeaderContext<'a> {
+    abi_version: u32,
+    /// 16 hex digits
+    api_hash: String,
+    last_error: bool,
+    string_out_len: bool,
+    /// Whether `WEAVEFFI_DEPRECATED` is needed
+    any_deprecated: bool,
+    /// Typedef names of the declared handle types
+    handle_types: Vec<String>,
+    modules: Vec<CModuleContext<'a>>,
 }
 
-fn render_module_header(out: &mut String, module: &Module) {
-    out.push_str(&format!("// Module: {}\n", module.name));
-    for f in &module.functions {
-        let mut params_sig = c_params_sig(&f.params);
-        // If return bytes, append out_len before out_err
-        let ret_sig = if let Some(ret) = &f.returns {
-            let (ret_ty, needs_len) = c_ret_type_for(ret);
-            if needs_len { params_sig.push("size_t* out_len".to_string()); }
-            ret_ty.to_string()
-        } else {
-            "void".to_string()
-        };
+#[derive(Serialize)]
+struct CModuleContext<'a> {
+    name: &'a str,
+    since: Option<&'a str>,
+    /// Deprecation note on one line; each function repeats it
+    deprecated: Option<String>,
+    doc: Vec<&'a str>,
+    errors: Option<CErrorsContext<'a>>,
+    functions: Vec<CFunctionContext>,
+}
+
+#[derive(Serialize)]
+struct CErrorsContext<'a> {
+    name: &'a str,
+    codes: Vec<CErrorCodeContext>,
+}
+
+#[derive(Serialize)]
+struct CErrorCodeContext {
+    /// Doc, or the message when there is none; safe inside `/** */`
+    doc: String,
+    constant: String,
+    value: i32,
+}
+
+#[derive(Serialize)]
+struct CFunctionContext {
+    /// Doxygen block, empty when there is nothing to say
+    doc_comment: String,
+    /// Escaped deprecation note
+    deprecated: Option<String>,
+    prototype: String,
+}
+
+pub fn render_c_header(api: &Api, renderer: &Renderer) -> Result<String> {
+    renderer.render("c/weaveffi.h.tera", &c_context(api))
+}
+
+pub fn render_c_convenience_c(api: &Api, renderer: &Renderer) -> Result<String> {
+    renderer.render("c/weaveffi.c.tera", &c_context(api))
+}
+
+fn c_context(api: &Api) -> CHeaderContext<'_> {
+    let modules = api.modules.iter().map(|m| CModuleContext {
+        name: &m.name,
+        since: m.since.as_deref(),
+        deprecated: m.deprecated.as_ref().map(|d| d.note().replace('\n', " ")),
+        doc: doc_lines(m.doc.as_deref()),
+        errors: m.errors.as_ref().filter(|e| !e.codes.is_empty()).map(|errors| CErrorsContext {
+            name: &errors.name,
+            codes: errors.codes.iter().map(|c| {
+                let doc = doc_lines(c.doc.as_deref());
+                let doc = if doc.is_empty() { c.message.clone() } else { doc.join(" ") };
+                CErrorCodeContext {
+                    doc: doc.replace("*/", "*\\/"),
+                    constant: format!("WEAVEFFI_{}_{}", m.name.to_shouty_snake_case(), c.name.to_shouty_snake_case()),
+                    value: c.code,
+                }
+            }).collect(),
+        }),
+        functions: m.functions.iter().map(|f| CFunctionContext {
+            doc_comment: block_comment("", &c_doc_lines(m, f)),
+            deprecated: effective_deprecation(m, f).map(|d| escape_literal(&d.note())),
+            prototype: c_prototype(m, f, api),
+        }).collect(),
+    }).collect();
+    CHeaderContext {
+        abi_version: ABI_VERSION,
+        api_hash: format!("{:016x}", api_hash(api)),
+        last_error: api.error_convention == ErrorConvention::LastError,
+        string_out_len: api.string_return == StringReturn::OutLen,
+        any_deprecated: api.modules.iter().any(|m| m.deprecated.is_some() || m.functions.iter().any(|f| f.deprecated.is_some())),
+        handle_types: declared_handles(api).map(|h| c_handle_type_name(&h.name)).collect(),
+        modules,
+    }
+}
+
+/// C prototype of `f` exactly as declared in `weaveffi.h`, without attributes.
+pub fn c_prototype(module: &Module, f: &Function, api: &Api) -> String {
+    let mut params_sig = c_params_sig(&f.params);
+    // If return bytes, append out_len before out_err
+    let ret_sig = if let Some(ret) = &f.returns {
+        let (ret_ty, needs_len) = c_ret_type_for(ret, api.string_return);
+        if needs_len { params_sig.push("size_t* out_len".to_string()); }
+        ret_ty.to_string()
+    } else {
+        "void".to_string()
+    };
+    if api.error_convention == ErrorConvention::OutParam {
         params_sig.push("weaveffi_error* out_err".to_string());
-        let fn_name = c_symbol_name(&module.name, &f.name);
-        out.push_str(&format!("{} {}({});\n", ret_sig, fn_name, params_sig.join(", ")));
     }
-    out.push_str("\n");
+    if params_sig.is_empty() { params_sig.push("void".to_string()); }
+    let fn_name = c_symbol_name(&module.name, &f.name);
+    format!("{} {}({});", ret_sig, fn_name, params_sig.join(", "))
 }
 
-pub fn render_c_convenience_c() -> String {
-    let mut out = String::new();
-    out.push_str("#include \"weaveffi.h\"\n\n");
-    out.push_str("// Optional convenience wrappers can be added here in future versions.\n");
-    out
+/// Templates of the WASM generator.
+pub const WASM_TEMPLATES: Bundled = &[
+    ("wasm/README.md.tera", include_str!("../templates/wasm/README.md.tera")),
+    ("wasm/weaveffi_wasm.js.tera", include_str!("../templates/wasm/weaveffi_wasm.js.tera")),
+];
+
+/// What a library must report to be loaded by the bindings; the context of
+/// templates that need nothing else.
+#[derive(Serialize)]
+pub struct CompatContext {
+    pub abi_version: u32,
+    /// 16 hex digits
+    pub api_hash: String,
 }
 
-pub fn render_wasm_readme() -> String {
-    let mut out = String::new();
-    out.push_str("# WeaveFFI WASM (experimental)\n\n");
-    out.push_str("This folder contains a minimal stub to help you load a `wasm32-unknown-unknown` build of your WeaveFFI library.\n\n");
-    out.push_str("Build (example):\n\n");
-    out.push_str("```") ; out.push_str("bash\n");
-    out.push_str("cargo build --target wasm32-unknown-unknown --release\n");
-    out.push_str("``" ); out.push_str("\n\n");
-    out.push_str("Then serve the `.wasm` and use `weaveffi_wasm.js` to load it.\n");
-    out
+impl CompatContext {
+    pub fn new(api: &Api) -> Self {
+        Self { abi_version: ABI_VERSION, api_hash: format!("{:016x}", api_hash(api)) }
+    }
 }
 
-pub fn render_wasm_js_stub() -> String {
-    let mut out = String::new();
-    out.push_str("// Minimal JS loader for WeaveFFI WASM\n");
-    out.push_str("export async function loadWeaveFFI(url) {\n");
-    out.push_str("  const response = await fetch(url);\n");
-    out.push_str("  const bytes = await response.arrayBuffer();\n");
-    out.push_str("  const { instance } = await WebAssembly.instantiate(bytes, {});\n");
-    out.push_str("  return instance.exports;\n");
-    out.push_str("}\n");
-    out
+pub fn render_wasm_readme(api: &Api, renderer: &Renderer) -> Result<String> {
+    renderer.render("wasm/README.md.tera", &CompatContext::new(api))
 }
 
-fn swift_type_for(t: &TypeRef) -> &'static str {
+pub fn render_wasm_js_stub(api: &Api, renderer: &Renderer) -> Result<String> {
+    renderer.render("wasm/weaveffi_wasm.js.tera", &CompatContext::new(api))
+}
+
+fn swift_type_for(t: &TypeRef) -> String {
     match t {
-        TypeRef::I32 => "Int32",
-        TypeRef::U32 => "UInt32",
-        TypeRef::I64 => "Int64",
-        TypeRef::F64 => "Double",
-        TypeRef::Bool => "Bool",
-        TypeRef::StringUtf8 => "String",
-        TypeRef::Bytes => "Data",
-        TypeRef::Handle => "UInt64",
+        TypeRef::I32 => "Int32".into(),
+        TypeRef::U32 => "UInt32".into(),
+        TypeRef::I64 => "Int64".into(),
+        TypeRef::F64 => "Double".into(),
+        TypeRef::Bool => "Bool".into(),
+        TypeRef::StringUtf8 => "String".into(),
+        TypeRef::Bytes => "Data".into(),
+        TypeRef::Handle => "UInt64".into(),
+        TypeRef::TypedHandle(h) => h.clone(),
     }
 }
 
+/// Public Swift declaration of `f` inside its module enum.
+pub fn swift_signature(f: &Function) -> String {
+    let params: Vec<String> = f.params.iter().map(|p| format!("{}: {}", p.name, swift_type_for(&p.ty))).collect();
+    let ret = f.returns.as_ref().map(swift_type_for).unwrap_or_else(|| "Void".into());
+    format!("public static func {}({}) throws -> {}", f.name, params.join(", "), ret)
+}
+
 fn swift_call_args_for_params(params: &[Param]) -> String {
     let mut out: Vec<String> = Vec::new();
     for p in params {
@@ -133,6 +302,9 @@ fn swift_call_args_for_params(params: &[Param]) -> String {
                 out.push(format!("{}_ptr", p.name));
                 out.push(format!("{}_len", p.name));
             }
+            TypeRef::TypedHandle(_) => {
+                out.push(format!("{}.raw", p.name));
+            }
             _ => {
                 out.push(p.name.clone());
             }
@@ -144,6 +316,14 @@ fn swift_call_args_for_params(params: &[Param]) -> String {
 fn swift_prep_params(params: &[Param]) -> String {
     let mut out = String::new();
     for p in params {
+        if p.ownership == Ownership::Borrowed {
+            // Zero-copy: pointers are taken inside `swift_borrow_scope`;
+            // `withUTF8` needs a mutable copy of the (copy-on-write) string
+            if p.ty == TypeRef::StringUtf8 {
+                out.push_str(&format!("        var {n} = {n}\n", n = p.name));
+            }
+            continue;
+        }
         match &p.ty {
             TypeRef::StringUtf8 => {
                 out.push_str(&format!(
@@ -163,16 +343,55 @@ fn swift_prep_params(params: &[Param]) -> String {
     out
 }
 
-fn swift_return_postprocess(ret: Option<&TypeRef>) -> (String, String) {
+/// Wrap `body` in a scope that exposes a borrowed param as `<name>_ptr`/`<name>_len`
+/// pointing straight into Swift-owned storage, valid only inside the closure.
+fn swift_borrow_scope(p: &Param, ret_swift: &str, body: &str) -> String {
+    let indented: String = body.lines().map(|l| format!("    {}\n", l)).collect();
+    let open = match p.ty {
+        TypeRef::StringUtf8 => format!(
+            "        return try {n}.withUTF8 {{ ({n}_buf: UnsafeBufferPointer<UInt8>) throws -> {r} in\n            let {n}_ptr = {n}_buf.baseAddress\n            let {n}_len = {n}_buf.count\n",
+            n = p.name, r = ret_swift,
+        ),
+        _ => format!(
+            "        return try {n}.withUnsafeBytes {{ ({n}_raw: UnsafeRawBufferPointer) throws -> {r} in\n            let {n}_ptr = {n}_raw.bindMemory(to: UInt8.self).baseAddress\n            let {n}_len = {n}_raw.count\n",
+            n = p.name, r = ret_swift,
+        ),
+    };
+    format!("{}{}        }}\n", open, indented)
+}
+
+fn swift_return_postprocess(ret: Option<&TypeRef>, ownership: Ownership, string_return: StringReturn) -> (String, String) {
     match ret {
         None => (String::from(""), String::from("")),
+        // `out_len` strings may contain NULs, so decode exactly `out_len` bytes
+        Some(TypeRef::StringUtf8) if string_return == StringReturn::OutLen => {
+            let free = if ownership == Ownership::Borrowed { "" } else { "        defer { weaveffi_free_string_len(rv, out_len) }\n" };
+            (
+                format!("        guard let rv = rv else {{ throw WeaveFFIError.error(code: -1, message: \"null string\") }}\n{}        return String(decoding: UnsafeRawBufferPointer(start: rv, count: out_len), as: UTF8.self)\n", free),
+                String::from("let rv")
+            )
+        }
+        // Swift strings cannot adopt a foreign buffer, so `transferred` copies like `owned`
+        Some(TypeRef::StringUtf8) if ownership == Ownership::Borrowed => (
+            String::from("        guard let rv = rv else { throw WeaveFFIError.error(code: -1, message: \"null string\") }\n        return String(cString: rv)\n"),
+            String::from("let rv")
+        ),
         Some(TypeRef::StringUtf8) => (
             String::from("        defer { weaveffi_free_string(rv) }\n        guard let rv = rv else { throw WeaveFFIError.error(code: -1, message: \"null string\") }\n        return String(cString: rv)\n"),
             String::from("let rv")
         ),
-        Some(TypeRef::Bytes) => (
-            String::from("        // Returned bytes not yet wrapped to Swift Data in this template\n        return ()\n"),
-            String::from("let _")
+        Some(TypeRef::Bytes) => {
+            let wrap = match ownership {
+                Ownership::Owned => "        defer { weaveffi_free_bytes(UnsafeMutablePointer(mutating: rv), out_len) }\n        return Data(bytes: rv, count: out_len)\n",
+                Ownership::Borrowed => "        return Data(bytes: rv, count: out_len)\n",
+                // Zero-copy: `Data` frees the Rust buffer when it is deallocated
+                Ownership::Transferred => "        return Data(bytesNoCopy: UnsafeMutableRawPointer(mutating: rv), count: out_len, deallocator: .custom { ptr, len in\n            weaveffi_free_bytes(ptr.assumingMemoryBound(to: UInt8.self), len)\n        })\n",
+            };
+            (format!("        guard let rv = rv else {{ return Data() }}\n{}", wrap), String::from("let rv"))
+        }
+        Some(TypeRef::TypedHandle(h)) => (
+            format!("        return {}(raw: rv)\n", h),
+            String::from("let rv")
         ),
         Some(_) => (
             String::from("        return rv\n"),
@@ -181,139 +400,197 @@ fn swift_return_postprocess(ret: Option<&TypeRef>) -> (String, String) {
     }
 }
 
-pub fn render_swift_wrapper(api: &Api) -> String {
-    let mut out = String::new();
-    out.push_str("import WeaveFFI\n\n");
-    out.push_str("public enum WeaveFFIError: Error, CustomStringConvertible {\n    case error(code: Int32, message: String)\n    public var description: String {\n        switch self { case let .error(code, message): return \"(\\(code)) \\ (message)\" }\n    }\n}\n\n");
-    out.push_str("@inline(__always)\nfunc check(_ err: inout weaveffi_error) throws {\n    if err.code != 0 {\n        let message = err.message.flatMap { String(cString: $0) } ?? \"\"\n        weaveffi_error_clear(&err)\n        throw WeaveFFIError.error(code: err.code, message: message)\n    }\n}\n\n");
-    for m in &api.modules {
-        let type_name = to_camel(&m.name);
-        out.push_str(&format!("public enum {} {{\n", type_name));
-        for f in &m.functions {
-            // signature
-            let mut params_sig: Vec<String> = Vec::new();
-            for p in &f.params {
-                params_sig.push(format!("{} {}", p.name, swift_type_for(&p.ty)));
-            }
-            let ret_swift = f.returns.as_ref().map(|t| swift_type_for(t)).unwrap_or("Void");
-            out.push_str(&format!("    public static func {}({}) throws -> {} {{\n", f.name, params_sig.join(", "), ret_swift));
-            out.push_str("        var err = weaveffi_error(code: 0, message: nil)\n");
-            out.push_str(&swift_prep_params(&f.params));
-            let call_args = swift_call_args_for_params(&f.params);
-            // bytes return not implemented ergonomically yet; keep placeholder
-            let (ret_post, let_rv) = swift_return_postprocess(f.returns.as_ref());
-            match f.returns.as_ref() {
-                None => {
-                    out.push_str(&format!("        {}( {}, &err )\n", c_symbol_name(&m.name, &f.name), call_args));
-                    out.push_str("        try check(&err)\n");
-                    out.push_str("    }\n");
-                }
-                Some(_) => {
-                    out.push_str(&format!("        {} = {}( {}, &err )\n", let_rv, c_symbol_name(&m.name, &f.name), call_args));
-                    out.push_str("        try check(&err)\n");
-                    out.push_str(&ret_post);
-                    out.push_str("    }\n");
-                }
+/// Templates of the Swift generator.
+pub const SWIFT_TEMPLATES: Bundled = &[
+    ("swift/Package.swift.tera", include_str!("../templates/swift/Package.swift.tera")),
+    ("swift/module.modulemap.tera", include_str!("../templates/swift/module.modulemap.tera")),
+    ("swift/wrapper.swift.tera", include_str!("../templates/swift/wrapper.swift.tera")),
+];
+
+/// Context of `swift/wrapper.swift.tera`.
+#[derive(Serialize)]
+struct SwiftContext<'a> {
+    /// The C module to import
+    module: &'a str,
+    abi_version: u32,
+    /// 16 hex digits
+    api_hash: String,
+    last_error: bool,
+    handle_types: Vec<&'a str>,
+    modules: Vec<SwiftModuleContext>,
+}
+
+#[derive(Serialize)]
+struct SwiftModuleContext {
+    type_name: String,
+    /// `///` lines, possibly none
+    doc_comment: String,
+    /// `@available` line, or empty
+    available: String,
+    functions: Vec<SwiftFunctionContext>,
+}
+
+#[derive(Serialize)]
+struct SwiftFunctionContext {
+    doc_comment: String,
+    available: String,
+    signature: String,
+    /// Statements after the compatibility check and error slot, indented
+    body: String,
+}
+
+/// Swift wrapper over the C module `module` (the SwiftPM system library).
+pub fn render_swift_wrapper(api: &Api, module: &str, renderer: &Renderer) -> Result<String> {
+    let last_error = api.error_convention == ErrorConvention::LastError;
+    let modules = api.modules.iter().map(|m| SwiftModuleContext {
+        type_name: to_camel(&m.name),
+        doc_comment: swift_doc_comment("", doc_lines(m.doc.as_deref()), &[], m.since.as_deref()),
+        available: m.deprecated.as_ref().map(|d| swift_available(d, d.replacement.as_deref().map(to_camel).as_deref(), "")).unwrap_or_default(),
+        functions: m.functions.iter().map(|f| {
+            let params: Vec<(&str, String)> = f.params.iter()
+                .filter_map(|p| Some((p.name.as_str(), p.doc.as_deref().map(|d| doc_lines(Some(d)).join(" "))?)))
+                .collect();
+            SwiftFunctionContext {
+                doc_comment: swift_doc_comment("    ", doc_lines(f.doc.as_deref()), &params, f.since.as_deref()),
+                available: f.deprecated.as_ref().map(|d| swift_available(d, d.replacement.as_deref(), "    ")).unwrap_or_default(),
+                signature: swift_signature(f),
+                body: swift_function_body(api, m, f, last_error),
             }
+        }).collect(),
+    }).collect();
+    let context = SwiftContext {
+        module,
+        abi_version: ABI_VERSION,
+        api_hash: format!("{:016x}", api_hash(api)),
+        last_error,
+        handle_types: declared_handles(api).map(|h| h.name.as_str()).collect(),
+        modules,
+    };
+    renderer.render("swift/wrapper.swift.tera", &context)
+}
+
+fn swift_function_body(api: &Api, m: &Module, f: &Function, last_error: bool) -> String {
+    let (err_arg, check_call) = if last_error { ("", "        try check()\n") } else { ("&err", "        try check(&err)\n") };
+    let ret_swift = f.returns.as_ref().map(swift_type_for).unwrap_or_else(|| "Void".into());
+    let mut out = swift_prep_params(&f.params);
+    let returns_len = f.returns.as_ref().is_some_and(|t| c_ret_type_for(t, api.string_return).1);
+    if returns_len {
+        out.push_str("        var out_len: Int = 0\n");
+    }
+    let call_args = [swift_call_args_for_params(&f.params), if returns_len { "&out_len".into() } else { String::new() }, err_arg.to_string()]
+        .into_iter()
+        .filter(|a| !a.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let (ret_post, let_rv) = swift_return_postprocess(f.returns.as_ref(), f.return_ownership, api.string_return);
+    let mut body = String::new();
+    match f.returns.as_ref() {
+        None => {
+            body.push_str(&format!("        {}( {} )\n", c_symbol_name(&m.name, &f.name), call_args));
+            body.push_str(check_call);
+        }
+        Some(_) => {
+            body.push_str(&format!("        {} = {}( {} )\n", let_rv, c_symbol_name(&m.name, &f.name), call_args));
+            body.push_str(check_call);
+            body.push_str(&ret_post);
         }
-        out.push_str("}\n\n");
     }
+    for p in f.params.iter().rev().filter(|p| p.ownership == Ownership::Borrowed) {
+        body = swift_borrow_scope(p, &ret_swift, &body);
+    }
+    out.push_str(&body);
     out
 }
 
-fn to_camel(s: &str) -> String {
-    let mut it = s.split('_');
-    let mut out = String::new();
-    if let Some(first) = it.next() { out.push_str(&first[..1].to_uppercase()); out.push_str(&first[1..]); }
-    for part in it { out.push_str(&part[..1].to_uppercase()); out.push_str(&part[1..]); }
-    out
+/// DocC `///` comment with `- Parameter` entries and an availability note.
+fn swift_doc_comment(indent: &str, doc: Vec<&str>, params: &[(&str, String)], since: Option<&str>) -> String {
+    let mut lines: Vec<String> = doc.into_iter().map(String::from).collect();
+    let mut paragraph = |mut extra: Vec<String>| {
+        if extra.is_empty() { return; }
+        if !lines.is_empty() { lines.push(String::new()); }
+        lines.append(&mut extra);
+    };
+    paragraph(since.map(|s| format!("Available since {}.", s)).into_iter().collect());
+    paragraph(params.iter().map(|(n, d)| format!("- Parameter {}: {}", n, d)).collect());
+    lines.iter().map(|l| if l.is_empty() { format!("{}///\n", indent) } else { format!("{}/// {}\n", indent, l) }).collect()
 }
 
-fn ffi_napi_type_for(t: &TypeRef) -> &'static str {
-    match t {
-        TypeRef::I32 => "int",
-        TypeRef::U32 => "uint",
-        TypeRef::I64 => "int64",
-        TypeRef::F64 => "double",
-        TypeRef::Bool => "bool",
-        TypeRef::StringUtf8 => "CString", // const char*
-        TypeRef::Bytes => "pointer",      // const uint8_t*
-        TypeRef::Handle => "uint64",
-    }
+fn swift_available(d: &Deprecation, renamed: Option<&str>, indent: &str) -> String {
+    let renamed = renamed.map(|r| format!(", renamed: \"{}\"", r)).unwrap_or_default();
+    format!("{}@available(*, deprecated{}, message: \"{}\")\n", indent, renamed, escape_literal(&d.note()))
 }
 
-pub fn render_node_index_ts(api: &Api) -> String {
+/// Swift and Kotlin type name for a module, e.g. `calculator_v2` -> `CalculatorV2`.
+pub(crate) fn to_camel(s: &str) -> String {
     let mut out = String::new();
-    out.push_str("import ffi from 'ffi-napi'\n");
-    out.push_str("import ref from 'ref-napi'\n\n");
-    out.push_str("const libPath = process.env.WEAVEFFI_LIB || './libweaveffi.dylib'\n");
-    out.push_str("const CString = ref.types.CString as any\n");
-    out.push_str("const bool = ref.types.bool as any\n");
-    out.push_str("const uint = ref.types.uint as any\n");
-    out.push_str("const int = ref.types.int as any\n");
-    out.push_str("const int64 = ref.types.int64 as any\n");
-    out.push_str("const uint64 = ref.types.uint64 as any\n");
-    out.push_str("const double = ref.types.double as any\n");
-    out.push_str("const size_t = ref.types.size_t as any\n");
-    out.push_str("const pointer = ref.refType(ref.types.void) as any\n\n");
-    out.push_str("export const lib = ffi.Library(libPath, {\n");
-    // memory helpers
-    out.push_str("  'weaveffi_free_string': ['void', [CString]],\n");
-    out.push_str("  'weaveffi_free_bytes': ['void', [pointer, size_t]],\n");
-    out.push_str("  'weaveffi_error_clear': ['void', [pointer]],\n");
-    for m in &api.modules {
-        for f in &m.functions {
-            let sym = c_symbol_name(&m.name, &f.name);
-            let (ret_ty, needs_len) = f.returns.as_ref().map(|t| c_ret_type_for(t)).unwrap_or(("void", false));
-            let ts_ret = match ret_ty { "const char*" => "CString", "const uint8_t*" => "pointer", "void" => "void", other if other == "bool" => "bool", other => other };
-            let mut args: Vec<String> = Vec::new();
-            for p in &f.params {
-                match p.ty {
-                    TypeRef::StringUtf8 | TypeRef::Bytes => { args.push("pointer".into()); args.push("size_t".into()); }
-                    _ => { args.push(ffi_napi_type_for(&p.ty).into()); }
-                }
-            }
-            if needs_len { args.push("pointer".into()); }
-            args.push("pointer".into()); // out_err
-            // Return type: use variable (e.g., CString, int, pointer) except for 'void' which is allowed as a string
-            if ts_ret == "void" {
-                out.push_str(&format!("  '{}': ['void', [{}]],\n", sym, args.join(", ")));
-            } else {
-                out.push_str(&format!("  '{}': [{}, [{}]],\n", sym, ts_ret, args.join(", ")));
-            }
+    for part in s.split('_') {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
         }
     }
-    out.push_str("})\n\n");
-    out.push_str("export default lib\n");
     out
 }
 
-pub fn render_node_dts(api: &Api) -> String {
-    let mut out = String::from("// Generated types for WeaveFFI functions\n");
-    for m in &api.modules {
-        out.push_str(&format!("// module {}\n", m.name));
-        for f in &m.functions {
-            let mut params: Vec<String> = Vec::new();
-            for p in &f.params {
-                let ts = match p.ty {
-                    TypeRef::I32 | TypeRef::U32 | TypeRef::I64 | TypeRef::F64 => "number",
-                    TypeRef::Bool => "boolean",
-                    TypeRef::StringUtf8 => "string",
-                    TypeRef::Bytes => "Buffer",
-                    TypeRef::Handle => "number",
-                };
-                params.push(format!("{}: {}", p.name, ts));
-            }
-            let ret = match f.returns.as_ref() {
-                None => "void",
-                Some(TypeRef::I32 | TypeRef::U32 | TypeRef::I64 | TypeRef::F64) => "number",
-                Some(TypeRef::Bool) => "boolean",
-                Some(TypeRef::StringUtf8) => "string",
-                Some(TypeRef::Bytes) => "Buffer",
-                Some(TypeRef::Handle) => "number",
-            };
-            out.push_str(&format!("export function {}({}): {}\n", f.name, params.join(", "), ret));
-        }
+fn ts_type_for(t: &TypeRef) -> String {
+    match t {
+        TypeRef::I32 | TypeRef::U32 | TypeRef::I64 | TypeRef::F64 => "number".into(),
+        TypeRef::Bool => "boolean".into(),
+        TypeRef::StringUtf8 => "string".into(),
+        TypeRef::Bytes => "Buffer".into(),
+        TypeRef::Handle => "number".into(),
+        TypeRef::TypedHandle(h) => h.clone(),
     }
-    out
+}
+
+/// TypeScript declaration of `f` as exported from `types.d.ts`.
+pub fn ts_signature(f: &Function) -> String {
+    let params: Vec<String> = f.params.iter().map(|p| format!("{}: {}", p.name, ts_type_for(&p.ty))).collect();
+    let ret = f.returns.as_ref().map(ts_type_for).unwrap_or_else(|| "void".into());
+    format!("function {}({}): {}", f.name, params.join(", "), ret)
+}
+
+/// Templates of the Node generator.
+pub const NODE_TEMPLATES: Bundled = &[
+    ("node/index.js.tera", include_str!("../templates/node/index.js.tera")),
+    ("node/types.d.ts.tera", include_str!("../templates/node/types.d.ts.tera")),
+    ("node/package.json.tera", include_str!("../templates/node/package.json.tera")),
+];
+
+/// Context of `node/types.d.ts.tera`.
+#[derive(Serialize)]
+struct NodeTypesContext<'a> {
+    handle_types: Vec<&'a str>,
+    modules: Vec<NodeModuleContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct NodeModuleContext<'a> {
+    name: &'a str,
+    doc: Vec<&'a str>,
+    functions: Vec<SignatureContext>,
+}
+
+/// A declaration and the doc comment that precedes it.
+#[derive(Serialize)]
+struct SignatureContext {
+    /// Empty, or complete lines
+    doc_comment: String,
+    signature: String,
+}
+
+pub fn render_node_dts(api: &Api, renderer: &Renderer) -> Result<String> {
+    let context = NodeTypesContext {
+        handle_types: declared_handles(api).map(|h| h.name.as_str()).collect(),
+        modules: api.modules.iter().map(|m| NodeModuleContext {
+            name: &m.name,
+            doc: doc_lines(m.doc.as_deref()),
+            functions: m.functions.iter().map(|f| SignatureContext {
+                doc_comment: block_comment("", &function_doc_lines(m, f, |p, d| format!("@param {} - {}", p, d), true)),
+                signature: ts_signature(f),
+            }).collect(),
+        }).collect(),
+    };
+    renderer.render("node/types.d.ts.tera", &context)
 }
@@ -1,5 +1,6 @@
+use crate::templates::to_camel;
 use std::collections::{BTreeMap, BTreeSet};
-use weaveffi_ir::ir::{Api, ErrorDomain, Function, Module, Param, TypeRef};
+use weaveffi_ir::ir::{Api, Deprecation, ErrorDomain, Function, HandleType, Module, Ownership, Param, TypeRef};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ValidationError {
@@ -23,6 +24,28 @@ pub enum ValidationError {
     InvalidErrorCode { module: String, name: String },
     #[error("function name collides with error domain name in module '{module}': {name}")]
     NameCollisionWithErrorDomain { module: String, name: String },
+    #[error("duplicate handle type: {0}")] DuplicateHandleName(String),
+    #[error("handle type name is reserved for generated code: {0}")] ReservedHandleName(String),
+    #[error("handle type '{handle}' has the same name as the generated type of module '{module}'")]
+    HandleNameCollidesWithModule { handle: String, module: String },
+    #[error("function {module}::{function} collides with the generated native '{native}'")]
+    ReservedFunctionName { module: String, function: String, native: String },
+    #[error("handle type '{name}' in module '{module}' has no release function")]
+    HandleMissingRelease { module: String, name: String },
+    #[error("release function '{function}' for handle type '{handle}' not found in module '{module}'")]
+    UnknownReleaseFunction { module: String, handle: String, function: String },
+    #[error("release function '{module}::{function}' must take a single handle<{handle}> parameter")]
+    InvalidReleaseFunction { module: String, handle: String, function: String },
+    #[error("unknown handle type in {module}::{function}: handle<{name}>")]
+    UnknownHandleType { module: String, function: String, name: String },
+    #[error("ownership in {module}::{function} only applies to string or bytes: {item}")]
+    OwnershipOnNonBuffer { module: String, function: String, item: String },
+    #[error("transferred ownership is only supported for return values: {module}::{function}({param})")]
+    TransferredParam { module: String, function: String, param: String },
+    #[error("invalid version '{version}' on {item}: expected semver, e.g. \"0.3.0\"")]
+    InvalidVersion { item: String, version: String },
+    #[error("deprecated {item} names unknown replacement '{replacement}'")]
+    UnknownReplacement { item: String, replacement: String },
 }
 
 const RESERVED: &[&str] = &[
@@ -30,18 +53,76 @@ const RESERVED: &[&str] = &[
     "await", "break", "continue", "fn", "struct", "enum", "mod", "use",
 ];
 
+/// Types the host bindings declare next to the handle wrappers.
+const RESERVED_TYPES: &[&str] = &["WeaveFFI", "WeaveFFIError", "WeaveFFIException"];
+
+/// Natives of the Kotlin `WeaveFFI` class. Functions taking or returning typed
+/// handles get a `Native` suffix on their external declaration, so both forms
+/// of a name are rejected.
+const RESERVED_NATIVES: &[&str] = &["abiVersionNative", "apiHashNative"];
+
 pub fn validate_api(api: &Api) -> Result<(), ValidationError> {
+    // Handle types are referenced across modules, so collect them up front
+    let mut handles = BTreeSet::new();
+    for h in api.modules.iter().flat_map(|m| m.handles.iter()) {
+        if !handles.insert(h.name.clone()) {
+            return Err(ValidationError::DuplicateHandleName(h.name.clone()));
+        }
+    }
+
+    // All modules share the Kotlin `WeaveFFI` class, so these natives clash across modules
+    let natives: BTreeSet<String> = api.modules.iter()
+        .flat_map(|m| m.functions.iter())
+        .filter(|f| f.params.iter().map(|p| &p.ty).chain(f.returns.as_ref()).any(|t| matches!(t, TypeRef::TypedHandle(_))))
+        .map(|f| format!("{}Native", f.name))
+        .collect();
+
     let mut module_names = BTreeSet::new();
     for m in &api.modules {
         if !module_names.insert(m.name.clone()) {
             return Err(ValidationError::DuplicateModuleName(m.name.clone()));
         }
-        validate_module(m)?;
+        validate_module(m, &handles, &natives)?;
+    }
+    // Handles are emitted bare as Swift structs and Kotlin value classes, next to
+    // the module types and the shared WeaveFFI types
+    for name in &handles {
+        if RESERVED_TYPES.contains(&name.as_str()) {
+            return Err(ValidationError::ReservedHandleName(name.clone()));
+        }
+        if let Some(m) = api.modules.iter().find(|m| &to_camel(&m.name) == name) {
+            return Err(ValidationError::HandleNameCollidesWithModule { handle: name.clone(), module: m.name.clone() });
+        }
+    }
+    // Module replacements name other modules, so check them once all names are known
+    for m in &api.modules {
+        let item = format!("module {}", m.name);
+        validate_availability(&item, m.since.as_deref(), m.deprecated.as_ref(), |r| module_names.contains(r) && r != m.name)?;
+    }
+    Ok(())
+}
+
+fn validate_availability(
+    item: &str,
+    since: Option<&str>,
+    deprecated: Option<&Deprecation>,
+    replacement_exists: impl Fn(&str) -> bool,
+) -> Result<(), ValidationError> {
+    let versions = since.into_iter().chain(deprecated.and_then(|d| d.since.as_deref()));
+    for version in versions {
+        if semver::Version::parse(version).is_err() {
+            return Err(ValidationError::InvalidVersion { item: item.to_string(), version: version.to_string() });
+        }
+    }
+    if let Some(replacement) = deprecated.and_then(|d| d.replacement.as_deref()) {
+        if !replacement_exists(replacement) {
+            return Err(ValidationError::UnknownReplacement { item: item.to_string(), replacement: replacement.to_string() });
+        }
     }
     Ok(())
 }
 
-fn validate_module(module: &Module) -> Result<(), ValidationError> {
+fn validate_module(module: &Module, handles: &BTreeSet<String>, natives: &BTreeSet<String>) -> Result<(), ValidationError> {
     if module.name.trim().is_empty() {
         return Err(ValidationError::NoModuleName);
     }
@@ -54,7 +135,15 @@ fn validate_module(module: &Module) -> Result<(), ValidationError> {
         if !function_names.insert(f.name.clone()) {
             return Err(ValidationError::DuplicateFunctionName { module: module.name.clone(), function: f.name.clone() });
         }
-        validate_function(module, f)?;
+        validate_function(module, f, handles, natives)?;
+        let item = format!("function {}::{}", module.name, f.name);
+        validate_availability(&item, f.since.as_deref(), f.deprecated.as_ref(), |r| {
+            r != f.name && module.functions.iter().any(|g| g.name == r)
+        })?;
+    }
+
+    for h in &module.handles {
+        validate_handle_type(module, h)?;
     }
 
     if let Some(errors) = &module.errors {
@@ -64,17 +153,23 @@ fn validate_module(module: &Module) -> Result<(), ValidationError> {
     Ok(())
 }
 
-fn validate_function(module: &Module, f: &Function) -> Result<(), ValidationError> {
+fn validate_function(module: &Module, f: &Function, handles: &BTreeSet<String>, natives: &BTreeSet<String>) -> Result<(), ValidationError> {
     if RESERVED.contains(&f.name.as_str()) {
         return Err(ValidationError::ReservedKeyword(f.name.clone()));
     }
+    if let Some(native) = [f.name.clone(), format!("{}Native", f.name)].into_iter().find(|n| RESERVED_NATIVES.contains(&n.as_str())) {
+        return Err(ValidationError::ReservedFunctionName { module: module.name.clone(), function: f.name.clone(), native });
+    }
+    if natives.contains(&f.name) {
+        return Err(ValidationError::ReservedFunctionName { module: module.name.clone(), function: f.name.clone(), native: f.name.clone() });
+    }
     if f.r#async {
         return Err(ValidationError::AsyncNotSupported { module: module.name.clone(), function: f.name.clone() });
     }
 
     let mut param_names = BTreeSet::new();
     for p in &f.params {
-        validate_param(module, &f.name, p)?;
+        validate_param(module, &f.name, p, handles)?;
         if !param_names.insert(p.name.clone()) {
             return Err(ValidationError::DuplicateParamName { module: module.name.clone(), function: f.name.clone(), param: p.name.clone() });
         }
@@ -82,22 +177,55 @@ fn validate_function(module: &Module, f: &Function) -> Result<(), ValidationErro
 
     // Returns type is already constrained by TypeRef enum; enforce no nested structs (not present in v0)
     if let Some(ret) = &f.returns {
-        validate_type_ref(ret);
+        validate_type_ref(module, &f.name, ret, handles)?;
+    }
+    if f.return_ownership != Ownership::Owned && !matches!(f.returns, Some(TypeRef::StringUtf8 | TypeRef::Bytes)) {
+        return Err(ValidationError::OwnershipOnNonBuffer { module: module.name.clone(), function: f.name.clone(), item: "return".into() });
     }
 
     Ok(())
 }
 
-fn validate_param(_module: &Module, _function: &str, p: &Param) -> Result<(), ValidationError> {
+fn validate_param(module: &Module, function: &str, p: &Param, handles: &BTreeSet<String>) -> Result<(), ValidationError> {
     if RESERVED.contains(&p.name.as_str()) {
         return Err(ValidationError::ReservedKeyword(p.name.clone()));
     }
-    validate_type_ref(&p.ty);
-    Ok(())
+    match p.ownership {
+        Ownership::Owned => {}
+        Ownership::Transferred => {
+            return Err(ValidationError::TransferredParam { module: module.name.clone(), function: function.to_string(), param: p.name.clone() });
+        }
+        Ownership::Borrowed if !matches!(p.ty, TypeRef::StringUtf8 | TypeRef::Bytes) => {
+            return Err(ValidationError::OwnershipOnNonBuffer { module: module.name.clone(), function: function.to_string(), item: p.name.clone() });
+        }
+        Ownership::Borrowed => {}
+    }
+    validate_type_ref(module, function, &p.ty, handles)
+}
+
+fn validate_type_ref(module: &Module, function: &str, t: &TypeRef, handles: &BTreeSet<String>) -> Result<(), ValidationError> {
+    match t {
+        TypeRef::TypedHandle(name) if !handles.contains(name) => Err(ValidationError::UnknownHandleType {
+            module: module.name.clone(),
+            function: function.to_string(),
+            name: name.clone(),
+        }),
+        _ => Ok(()),
+    }
 }
 
-fn validate_type_ref(_t: &TypeRef) {
-    // Currently no-op since TypeRef only includes supported primitives/handles
+fn validate_handle_type(module: &Module, h: &HandleType) -> Result<(), ValidationError> {
+    let Some(release) = &h.release else {
+        return Err(ValidationError::HandleMissingRelease { module: module.name.clone(), name: h.name.clone() });
+    };
+    let Some(f) = module.functions.iter().find(|f| &f.name == release) else {
+        return Err(ValidationError::UnknownReleaseFunction { module: module.name.clone(), handle: h.name.clone(), function: release.clone() });
+    };
+    let takes_handle = matches!(f.params.as_slice(), [p] if p.ty == TypeRef::TypedHandle(h.name.clone()));
+    if !takes_handle {
+        return Err(ValidationError::InvalidReleaseFunction { module: module.name.clone(), handle: h.name.clone(), function: release.clone() });
+    }
+    Ok(())
 }
 
 fn validate_error_domain(module: &Module, errors: &ErrorDomain, function_names: &BTreeSet<String>) -> Result<(), ValidationError> {
@@ -124,3 +252,35 @@ fn validate_error_domain(module: &Module, errors: &ErrorDomain, function_names:
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weaveffi_ir::parse::parse_api_str;
+
+    fn check(yaml: &str) -> Result<(), ValidationError> {
+        validate_api(&parse_api_str(yaml, "yaml").unwrap())
+    }
+
+    #[test]
+    fn generated_names_are_reserved() {
+        let handle = |name: &str| format!(
+            "version: \"0.1.0\"\nmodules:\n  - name: files\n    handles: [{{ name: {name}, release: close }}]\n    functions:\n      - {{ name: close, params: [{{ name: h, type: \"handle<{name}>\" }}] }}\n"
+        );
+        assert!(check(&handle("Document")).is_ok());
+        assert!(matches!(check(&handle("Files")), Err(ValidationError::HandleNameCollidesWithModule { .. })));
+        assert!(matches!(check(&handle("WeaveFFIError")), Err(ValidationError::ReservedHandleName(_))));
+        assert!(matches!(check(&handle("WeaveFFIException")), Err(ValidationError::ReservedHandleName(_))));
+
+        let function = |name: &str| format!("version: \"0.1.0\"\nmodules:\n  - name: m\n    functions:\n      - {{ name: {name}, params: [] }}\n");
+        assert!(check(&function("abi_version")).is_ok());
+        assert!(matches!(check(&function("abiVersion")), Err(ValidationError::ReservedFunctionName { .. })));
+        assert!(matches!(check(&function("apiHashNative")), Err(ValidationError::ReservedFunctionName { .. })));
+
+        let native = |name: &str| format!(
+            "version: \"0.1.0\"\nmodules:\n  - name: files\n    handles: [{{ name: File, release: close }}]\n    functions:\n      - {{ name: close, params: [{{ name: h, type: \"handle<File>\" }}] }}\n      - {{ name: {name}, params: [] }}\n"
+        );
+        assert!(check(&native("closeNow")).is_ok());
+        assert!(matches!(check(&native("closeNative")), Err(ValidationError::ReservedFunctionName { .. })));
+    }
+}
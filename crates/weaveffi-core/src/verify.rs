@@ -0,0 +1,69 @@
+//! Compare the symbols a built library exports with those an API declares.
+use std::collections::BTreeSet;
+use weaveffi_ir::ir::{Api, ErrorConvention, StringReturn};
+
+/// Every symbol the generated header declares for `api`: the runtime helpers
+/// followed by one `weaveffi_<module>_<function>` per function.
+pub fn expected_symbols(api: &Api) -> Vec<String> {
+    let mut out: Vec<String> = [
+        "weaveffi_abi_version",
+        "weaveffi_api_hash",
+        "weaveffi_error_clear",
+        "weaveffi_free_string",
+        "weaveffi_free_bytes",
+        "weaveffi_set_allocator",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    if api.string_return == StringReturn::OutLen {
+        out.push("weaveffi_free_string_len".into());
+    }
+    if api.error_convention == ErrorConvention::LastError {
+        out.extend(["weaveffi_last_error_code", "weaveffi_last_error_message", "weaveffi_last_error_clear"].map(String::from));
+    }
+    for m in &api.modules {
+        for f in &m.functions {
+            out.push(format!("weaveffi_{}_{}", m.name, f.name));
+        }
+    }
+    out
+}
+
+/// Result of checking a library's exports against an API.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolReport {
+    /// Declared but not exported
+    pub missing: Vec<String>,
+    /// Exported `weaveffi_*` symbols the API does not declare
+    pub extra: Vec<String>,
+    /// `(expected, exported)` pairs that look like typos of each other
+    pub misspelled: Vec<(String, String)>,
+}
+
+impl SymbolReport {
+    /// Extra symbols alone are not fatal: libraries may export private helpers.
+    pub fn is_ok(&self) -> bool { self.missing.is_empty() && self.misspelled.is_empty() }
+}
+
+/// Check `exported` (plain C names, without any platform `_` prefix) against `api`.
+pub fn verify_symbols<'a>(api: &Api, exported: impl IntoIterator<Item = &'a str>) -> SymbolReport {
+    let expected = expected_symbols(api);
+    let exported: BTreeSet<&str> = exported.into_iter().collect();
+    let mut extra: Vec<String> = exported
+        .iter()
+        .filter(|s| s.starts_with("weaveffi_") && !expected.iter().any(|e| e == *s))
+        .map(|s| s.to_string())
+        .collect();
+    let mut report = SymbolReport::default();
+    for e in expected.iter().filter(|e| !exported.contains(e.as_str())) {
+        // A close unclaimed export is more likely a typo than a missing function
+        let near = extra.iter().position(|x| strsim::damerau_levenshtein(e, x) <= 2);
+        match near {
+            Some(i) => report.misspelled.push((e.clone(), extra.remove(i))),
+            None => report.missing.push(e.clone()),
+        }
+    }
+    report.extra = extra;
+    report
+}
@@ -1,11 +1,12 @@
 use anyhow::Result;
 use camino::Utf8Path;
+use weaveffi_ir::ir::Api;
+use crate::output::FileSet;
+use crate::render::Renderer;
 use crate::templates::{render_wasm_js_stub, render_wasm_readme};
 
-pub fn write_minimal_wasm_stub(out_dir: &Utf8Path) -> Result<()> {
-    let wasm_dir = out_dir.join("wasm");
-    std::fs::create_dir_all(&wasm_dir)?;
-    std::fs::write(wasm_dir.join("README.md"), render_wasm_readme())?;
-    std::fs::write(wasm_dir.join("weaveffi_wasm.js"), render_wasm_js_stub())?;
+pub fn write_minimal_wasm_stub(api: &Api, renderer: &Renderer, wasm_dir: &Utf8Path, files: &mut FileSet) -> Result<()> {
+    files.add(wasm_dir.join("README.md"), render_wasm_readme(api, renderer)?);
+    files.add(wasm_dir.join("weaveffi_wasm.js"), render_wasm_js_stub(api, renderer)?);
     Ok(())
 }
@@ -1,172 +1,250 @@
 use anyhow::Result;
-use camino::Utf8Path;
+use serde::Serialize;
 use tracing::info;
+use weaveffi_core::abi::ABI_VERSION;
 use weaveffi_core::codegen::Generator;
-use weaveffi_ir::ir::{Api, TypeRef};
+use weaveffi_core::config::{relative_path, GeneratorOptions};
+use weaveffi_core::output::FileSet;
+use weaveffi_core::render::{Bundled, Renderer};
+use weaveffi_core::compat::api_hash;
+use weaveffi_core::templates::{block_comment, doc_lines, effective_deprecation, escape_literal, function_doc_lines};
+use weaveffi_ir::ir::{Api, ErrorConvention, Function, Module, Ownership, Param, StringReturn, TypeRef};
 use std::fmt::Write as _;
 
 pub struct AndroidGenerator;
 
+/// Templates of the Android generator.
+pub const ANDROID_TEMPLATES: Bundled = &[
+    ("android/settings.gradle.tera", include_str!("../templates/android/settings.gradle.tera")),
+    ("android/build.gradle.tera", include_str!("../templates/android/build.gradle.tera")),
+    ("android/WeaveFFI.kt.tera", include_str!("../templates/android/WeaveFFI.kt.tera")),
+    ("android/CMakeLists.txt.tera", include_str!("../templates/android/CMakeLists.txt.tera")),
+    ("android/weaveffi_jni.c.tera", include_str!("../templates/android/weaveffi_jni.c.tera")),
+];
+
+/// Context of every Android template.
+#[derive(Serialize)]
+struct AndroidContext<'a> {
+    package: &'a str,
+    project: &'a str,
+    library: &'a str,
+    /// C header directory, relative to `CMakeLists.txt`
+    c_dir: String,
+    abi_version: u32,
+    /// 16 hex digits
+    api_hash: String,
+    string_out_len: bool,
+    /// JNI symbol prefix of the `WeaveFFI` class
+    jni_class: String,
+    /// JNI class name of `WeaveFFIException`
+    exception_class: String,
+    handle_types: Vec<&'a str>,
+    modules: Vec<ModuleContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct ModuleContext<'a> {
+    name: &'a str,
+    doc: Vec<&'a str>,
+    functions: Vec<FunctionContext>,
+}
+
+#[derive(Serialize)]
+struct FunctionContext {
+    /// KDoc and `@Deprecated` lines
+    annotations: String,
+    signature: String,
+    /// Call of the native method when the public function wraps it; without
+    /// typed handles the public function is the `external` one
+    wrapper: Option<String>,
+    native_name: String,
+    native_params: String,
+    native_return: &'static str,
+    jni_return: &'static str,
+    jni_params: String,
+    /// Indented statements of the JNI entry point
+    jni_body: String,
+}
+
 impl Generator for AndroidGenerator {
     fn name(&self) -> &'static str { "android" }
-    fn generate(&self, _api: &Api, out_dir: &Utf8Path) -> Result<()> {
+    // The JNI shim includes the C header
+    fn dependencies(&self) -> Vec<&str> { vec!["c"] }
+    fn templates(&self) -> Bundled { ANDROID_TEMPLATES }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
         info!("generating Android JNI + Gradle template");
-        let dir = out_dir.join("android");
-        std::fs::create_dir_all(&dir)?;
-        // settings.gradle
-        let settings = "rootProject.name = 'weaveffi'\n";
-        std::fs::write(dir.join("settings.gradle"), settings)?;
-        // build.gradle (library)
-        let build_gradle = r#"plugins {
-    id 'com.android.library'
-    id 'org.jetbrains.kotlin.android' version '1.9.22' apply false
-}
-
-android {
-    namespace 'com.weaveffi'
-    compileSdk 34
-    defaultConfig { minSdk 24 }
-}
-"#;
-        std::fs::write(dir.join("build.gradle"), build_gradle)?;
-        // Kotlin wrapper stub
-        let src_dir = dir.join("src/main/java/com/weaveffi");
-        std::fs::create_dir_all(&src_dir)?;
-        let mut kotlin = String::from("package com.weaveffi\n\nclass WeaveFFI {\n    companion object {\n        init { System.loadLibrary(\"weaveffi\") }\n\n");
-        for m in &_api.modules {
-            for f in &m.functions {
-                let mut params_sig: Vec<String> = Vec::new();
-                for p in &f.params {
-                    params_sig.push(format!("{}: {}", p.name, kotlin_type(&p.ty)));
-                }
-                let ret = f.returns.as_ref().map(kotlin_type).unwrap_or("Unit");
-                writeln!(kotlin, "        @JvmStatic external fun {}({}): {}", f.name, params_sig.join(", "), ret).ok();
+        let renderer = Renderer::new(ANDROID_TEMPLATES, options.templates.as_deref())?;
+        let package = &options.android.package;
+        let context = AndroidContext {
+            package,
+            project: &options.android.project,
+            library: options.android_library(),
+            c_dir: relative_path(&options.android.dir.join("src/main/cpp"), &options.c.dir).into_string(),
+            abi_version: ABI_VERSION,
+            api_hash: format!("{:016x}", api_hash(api)),
+            string_out_len: api.string_return == StringReturn::OutLen,
+            jni_class: format!("Java_{}_WeaveFFI", jni_mangle(package)),
+            exception_class: format!("{}/WeaveFFIException", package.replace('.', "/")),
+            handle_types: api.modules.iter().flat_map(|m| m.handles.iter()).map(|h| h.name.as_str()).collect(),
+            modules: api.modules.iter().map(|m| ModuleContext {
+                name: &m.name,
+                doc: doc_lines(m.doc.as_deref()),
+                functions: m.functions.iter().map(|f| function_context(api, m, f)).collect(),
+            }).collect(),
+        };
+        let dir = &options.android.dir;
+        files.add(dir.join("settings.gradle"), renderer.render("android/settings.gradle.tera", &context)?);
+        files.add(dir.join("build.gradle"), renderer.render("android/build.gradle.tera", &context)?);
+        let src_dir = dir.join("src/main/java").join(package.replace('.', "/"));
+        files.add(src_dir.join("WeaveFFI.kt"), renderer.render("android/WeaveFFI.kt.tera", &context)?);
+        // C JNI shim and CMakeLists
+        let jni_dir = dir.join("src/main/cpp");
+        files.add(jni_dir.join("CMakeLists.txt"), renderer.render("android/CMakeLists.txt.tera", &context)?);
+        files.add(jni_dir.join("weaveffi_jni.c"), renderer.render("android/weaveffi_jni.c.tera", &context)?);
+        Ok(())
+    }
+}
+
+fn function_context(api: &Api, m: &Module, f: &Function) -> FunctionContext {
+    let mut annotations = String::new();
+    write_kotlin_annotations(&mut annotations, m, f);
+    // The JNI entry point keeps raw `Long`s; a public wrapper takes the value classes
+    let wrapper = uses_typed_handles(f).then(|| {
+        let call_args: Vec<String> = f.params.iter().map(|p| match p.ty {
+            TypeRef::TypedHandle(_) => format!("{}.raw", p.name),
+            _ => p.name.clone(),
+        }).collect();
+        let call = format!("{}({})", jni_method_name(f), call_args.join(", "));
+        match f.returns.as_ref() {
+            Some(TypeRef::TypedHandle(h)) => format!("{}({})", h, call),
+            _ => call,
+        }
+    });
+    let native_params: Vec<String> = f.params.iter().map(|p| format!("{}: {}", p.name, kotlin_type(&p.ty))).collect();
+    let mut jni_params = vec!["JNIEnv* env".to_string(), "jclass clazz".to_string()];
+    jni_params.extend(f.params.iter().map(|p| format!("{} {}", jni_param_type(&p.ty), p.name)));
+    FunctionContext {
+        annotations,
+        signature: kotlin_signature(f),
+        wrapper,
+        native_name: jni_method_name(f),
+        native_params: native_params.join(", "),
+        native_return: f.returns.as_ref().map(kotlin_type).unwrap_or("Unit"),
+        jni_return: jni_ret_type(f.returns.as_ref()),
+        jni_params: jni_params.join(", "),
+        jni_body: jni_body(api, m, f),
+    }
+}
+
+/// Convert the params, call the C function, release the params, raise any
+/// error as a `WeaveFFIException` and convert the result.
+fn jni_body(api: &Api, m: &Module, f: &Function) -> String {
+    let last_error = api.error_convention == ErrorConvention::LastError;
+    let string_out_len = api.string_return == StringReturn::OutLen;
+    let fail = match f.returns {
+        None => "return;",
+        Some(TypeRef::StringUtf8 | TypeRef::Bytes) => "return NULL;",
+        Some(_) => "return 0;",
+    };
+    let mut jni_c = String::new();
+    if !last_error {
+        writeln!(jni_c, "    weaveffi_error err = {{0, NULL}};").ok();
+    }
+    write_param_acquires(&mut jni_c, &f.params);
+    // Call underlying C function
+    let c_sym = format!("weaveffi_{}_{}", m.name, f.name);
+    let mut call_args: Vec<String> = Vec::new();
+    for p in &f.params {
+        match p.ty {
+            TypeRef::StringUtf8 => {
+                call_args.push(format!("(const uint8_t*){n}_chars", n = p.name));
+                call_args.push(format!("(size_t){n}_len", n = p.name));
+            }
+            TypeRef::Bytes => {
+                call_args.push(format!("(const uint8_t*){n}_elems", n = p.name));
+                call_args.push(format!("(size_t){n}_len", n = p.name));
             }
+            TypeRef::Bool => call_args.push(format!("(bool)({} == JNI_TRUE)", p.name)),
+            TypeRef::I32 => call_args.push(format!("(int32_t){}", p.name)),
+            TypeRef::U32 => call_args.push(format!("(uint32_t){}", p.name)),
+            TypeRef::I64 => call_args.push(format!("(int64_t){}", p.name)),
+            TypeRef::F64 => call_args.push(format!("(double){}", p.name)),
+            TypeRef::Handle | TypeRef::TypedHandle(_) => call_args.push(format!("(weaveffi_handle_t){}", p.name)),
         }
-        kotlin.push_str("    }\n}\n");
-        std::fs::write(src_dir.join("WeaveFFI.kt"), kotlin)?;
-        // C JNI shim sample and CMakeLists
-        let jni_dir = dir.join("src/main/cpp");
-        std::fs::create_dir_all(&jni_dir)?;
-        let cmake = r#"cmake_minimum_required(VERSION 3.22)
-project(weaveffi)
-add_library(weaveffi SHARED weaveffi_jni.c)
-target_include_directories(weaveffi PRIVATE ../../../../c)
-"#;
-        std::fs::write(jni_dir.join("CMakeLists.txt"), cmake)?;
-        let mut jni_c = String::from("#include <jni.h>\n#include <stdbool.h>\n#include <stdint.h>\n#include <stddef.h>\n#include \"weaveffi.h\"\n\n");
-        for m in &_api.modules {
-            for f in &m.functions {
-                // Signature
-                let jret = jni_ret_type(f.returns.as_ref());
-                let mut jparams: Vec<String> = Vec::new();
-                jparams.push("JNIEnv* env".into());
-                jparams.push("jclass clazz".into());
-                for p in &f.params {
-                    jparams.push(format!("{} {}", jni_param_type(&p.ty), p.name));
-                }
-                writeln!(jni_c, "JNIEXPORT {} JNICALL Java_com_weaveffi_WeaveFFI_{}({}) {{", jret, f.name, jparams.join(", ")).ok();
-                // Prepare params
-                writeln!(jni_c, "    weaveffi_error err = {{0, NULL}};").ok();
-                for p in &f.params {
-                    match p.ty {
-                        TypeRef::StringUtf8 => {
-                            writeln!(jni_c, "    const char* {n}_chars = (*env)->GetStringUTFChars(env, {n}, NULL);", n = p.name).ok();
-                            writeln!(jni_c, "    jsize {n}_len = (*env)->GetStringUTFLength(env, {n});", n = p.name).ok();
-                        }
-                        TypeRef::Bytes => {
-                            writeln!(jni_c, "    jboolean {n}_is_copy = 0;", n = p.name).ok();
-                            writeln!(jni_c, "    jbyte* {n}_elems = (*env)->GetByteArrayElements(env, {n}, &{n}_is_copy);", n = p.name).ok();
-                            writeln!(jni_c, "    jsize {n}_len = (*env)->GetArrayLength(env, {n});", n = p.name).ok();
-                        }
-                        _ => {}
-                    }
-                }
-                // Call underlying C function
-                let c_sym = format!("weaveffi_{}_{}", m.name, f.name);
-                let mut call_args: Vec<String> = Vec::new();
-                for p in &f.params {
-                    match p.ty {
-                        TypeRef::StringUtf8 => {
-                            call_args.push(format!("(const uint8_t*){n}_chars", n = p.name));
-                            call_args.push(format!("(size_t){n}_len", n = p.name));
-                        }
-                        TypeRef::Bytes => {
-                            call_args.push(format!("(const uint8_t*){n}_elems", n = p.name));
-                            call_args.push(format!("(size_t){n}_len", n = p.name));
-                        }
-                        TypeRef::Bool => call_args.push(format!("(bool)({} == JNI_TRUE)", p.name)),
-                        TypeRef::I32 => call_args.push(format!("(int32_t){}", p.name)),
-                        TypeRef::U32 => call_args.push(format!("(uint32_t){}", p.name)),
-                        TypeRef::I64 => call_args.push(format!("(int64_t){}", p.name)),
-                        TypeRef::F64 => call_args.push(format!("(double){}", p.name)),
-                        TypeRef::Handle => call_args.push(format!("(weaveffi_handle_t){}", p.name)),
+    }
+    let needs_len = match f.returns {
+        Some(TypeRef::Bytes) => true,
+        Some(TypeRef::StringUtf8) => string_out_len,
+        _ => false,
+    };
+    if needs_len {
+        writeln!(jni_c, "    size_t out_len = 0;").ok();
+        // out_len goes before out_err
+        call_args.push("&out_len".into());
+    }
+    if !last_error {
+        call_args.push("&err".into());
+    }
+    match f.returns.as_ref() {
+        None => {
+            writeln!(jni_c, "    {}( {} );", c_sym, call_args.join(", ")).ok();
+            write_param_releases(&mut jni_c, &f.params);
+            write_error_throw(&mut jni_c, last_error, fail);
+            writeln!(jni_c, "    return;").ok();
+        }
+        Some(ret) => match ret {
+            TypeRef::StringUtf8 => {
+                writeln!(jni_c, "    const char* rv = {}( {} );", c_sym, call_args.join(", ")).ok();
+                write_param_releases(&mut jni_c, &f.params);
+                write_error_throw(&mut jni_c, last_error, fail);
+                writeln!(jni_c, "    if (!rv) {{ weaveffi_throw(env, -1, \"null string\"); return NULL; }}").ok();
+                // Java strings always copy; only borrowed returns skip the free
+                if string_out_len {
+                    writeln!(jni_c, "    jstring out = weaveffi_new_jstring(env, rv, out_len);").ok();
+                    if f.return_ownership != Ownership::Borrowed {
+                        writeln!(jni_c, "    weaveffi_free_string_len(rv, out_len);").ok();
                     }
-                }
-                let needs_len = matches!(f.returns, Some(TypeRef::Bytes));
-                if needs_len {
-                    writeln!(jni_c, "    size_t out_len = 0;").ok();
-                }
-                if f.returns.is_none() {
-                    writeln!(jni_c, "    {}( {}, &err );", c_sym, call_args.join(", ")).ok();
-                    write_error_throw(&mut jni_c);
-                    writeln!(jni_c, "    return;").ok();
                 } else {
-                    match f.returns.as_ref().unwrap() {
-                        TypeRef::StringUtf8 => {
-                            writeln!(jni_c, "    const char* rv = {}( {}, &err );", c_sym, call_args.join(", ")).ok();
-                            write_error_throw(&mut jni_c);
-                            writeln!(jni_c, "    jstring out = rv ? (*env)->NewStringUTF(env, rv) : (*env)->NewStringUTF(env, \"\");").ok();
-                            writeln!(jni_c, "    weaveffi_free_string(rv);").ok();
-                            writeln!(jni_c, "    return out;").ok();
-                        }
-                        TypeRef::Bytes => {
-                            // Append out_len before err
-                            let mut args = call_args.clone();
-                            args.push("&out_len".into());
-                            writeln!(jni_c, "    const uint8_t* rv = {}( {}, &err );", c_sym, args.join(", ")).ok();
-                            write_error_throw(&mut jni_c);
-                            writeln!(jni_c, "    jbyteArray out = (*env)->NewByteArray(env, (jsize)out_len);").ok();
-                            writeln!(jni_c, "    if (out && rv) {{ (*env)->SetByteArrayRegion(env, out, 0, (jsize)out_len, (const jbyte*)rv); }}").ok();
-                            writeln!(jni_c, "    weaveffi_free_bytes((uint8_t*)rv, (size_t)out_len);").ok();
-                            writeln!(jni_c, "    return out;").ok();
-                        }
-                        TypeRef::Bool => {
-                            writeln!(jni_c, "    bool rv = {}( {}, &err );", c_sym, call_args.join(", ")).ok();
-                            write_error_throw(&mut jni_c);
-                            writeln!(jni_c, "    return rv ? JNI_TRUE : JNI_FALSE;").ok();
-                        }
-                        TypeRef::I32 | TypeRef::U32 | TypeRef::I64 | TypeRef::F64 | TypeRef::Handle => {
-                            let jcast = match f.returns.as_ref().unwrap() {
-                                TypeRef::I32 | TypeRef::U32 => "(jint)",
-                                TypeRef::I64 | TypeRef::Handle => "(jlong)",
-                                TypeRef::F64 => "(jdouble)",
-                                _ => "",
-                            };
-                            writeln!(jni_c, "    auto rv = {}( {}, &err );", c_sym, call_args.join(", ")).ok();
-                            write_error_throw(&mut jni_c);
-                            writeln!(jni_c, "    return {} rv;", jcast).ok();
-                        }
+                    writeln!(jni_c, "    jstring out = (*env)->NewStringUTF(env, rv);").ok();
+                    if f.return_ownership != Ownership::Borrowed {
+                        writeln!(jni_c, "    weaveffi_free_string(rv);").ok();
                     }
                 }
-                // Release params
-                for p in &f.params {
-                    match p.ty {
-                        TypeRef::StringUtf8 => {
-                            writeln!(jni_c, "    (*env)->ReleaseStringUTFChars(env, {n}, {n}_chars);", n = p.name).ok();
-                        }
-                        TypeRef::Bytes => {
-                            writeln!(jni_c, "    (*env)->ReleaseByteArrayElements(env, {n}, {n}_elems, 0);", n = p.name).ok();
-                        }
-                        _ => {}
-                    }
+                writeln!(jni_c, "    return out;").ok();
+            }
+            TypeRef::Bytes => {
+                writeln!(jni_c, "    const uint8_t* rv = {}( {} );", c_sym, call_args.join(", ")).ok();
+                write_param_releases(&mut jni_c, &f.params);
+                write_error_throw(&mut jni_c, last_error, fail);
+                writeln!(jni_c, "    jbyteArray out = (*env)->NewByteArray(env, (jsize)out_len);").ok();
+                writeln!(jni_c, "    if (out && rv) {{ (*env)->SetByteArrayRegion(env, out, 0, (jsize)out_len, (const jbyte*)rv); }}").ok();
+                // A jbyteArray cannot adopt native memory, so transferred buffers are copied too
+                if f.return_ownership != Ownership::Borrowed {
+                    writeln!(jni_c, "    weaveffi_free_bytes((uint8_t*)rv, (size_t)out_len);").ok();
                 }
-                writeln!(jni_c, "}}\n").ok();
+                writeln!(jni_c, "    return out;").ok();
             }
-        }
-        std::fs::write(jni_dir.join("weaveffi_jni.c"), jni_c)?;
-        Ok(())
+            TypeRef::Bool => {
+                writeln!(jni_c, "    bool rv = {}( {} );", c_sym, call_args.join(", ")).ok();
+                write_param_releases(&mut jni_c, &f.params);
+                write_error_throw(&mut jni_c, last_error, fail);
+                writeln!(jni_c, "    return rv ? JNI_TRUE : JNI_FALSE;").ok();
+            }
+            TypeRef::I32 | TypeRef::U32 | TypeRef::I64 | TypeRef::F64 | TypeRef::Handle | TypeRef::TypedHandle(_) => {
+                let jcast = match ret {
+                    TypeRef::I32 | TypeRef::U32 => "(jint)",
+                    TypeRef::I64 | TypeRef::Handle | TypeRef::TypedHandle(_) => "(jlong)",
+                    TypeRef::F64 => "(jdouble)",
+                    _ => "",
+                };
+                writeln!(jni_c, "    auto rv = {}( {} );", c_sym, call_args.join(", ")).ok();
+                write_param_releases(&mut jni_c, &f.params);
+                write_error_throw(&mut jni_c, last_error, fail);
+                writeln!(jni_c, "    return {} rv;", jcast).ok();
+            }
+        },
     }
+    jni_c
 }
 
 fn kotlin_type(t: &TypeRef) -> &'static str {
@@ -178,14 +256,59 @@ fn kotlin_type(t: &TypeRef) -> &'static str {
         TypeRef::Bool => "Boolean",
         TypeRef::StringUtf8 => "String",
         TypeRef::Bytes => "ByteArray",
-        TypeRef::Handle => "Long",
+        TypeRef::Handle | TypeRef::TypedHandle(_) => "Long",
     }
 }
 
+/// Type exposed by the public Kotlin API: typed handles use their value class.
+fn kotlin_api_type(t: &TypeRef) -> String {
+    match t {
+        TypeRef::TypedHandle(h) => h.clone(),
+        other => kotlin_type(other).to_string(),
+    }
+}
+
+/// Public Kotlin declaration of `f` on the `WeaveFFI` companion object.
+pub fn kotlin_signature(f: &Function) -> String {
+    let params: Vec<String> = f.params.iter().map(|p| format!("{}: {}", p.name, kotlin_api_type(&p.ty))).collect();
+    let ret = f.returns.as_ref().map(kotlin_api_type).unwrap_or_else(|| "Unit".into());
+    format!("fun {}({}): {}", f.name, params.join(", "), ret)
+}
+
+fn uses_typed_handles(f: &Function) -> bool {
+    f.params.iter().map(|p| &p.ty).chain(f.returns.as_ref()).any(|t| matches!(t, TypeRef::TypedHandle(_)))
+}
+
+/// KDoc and `@Deprecated` for the public Kotlin entry point of `f`.
+fn write_kotlin_annotations(out: &mut String, module: &Module, f: &Function) {
+    out.push_str(&block_comment("        ", &function_doc_lines(module, f, |p, d| format!("@param {} {}", p, d), false)));
+    let Some(d) = effective_deprecation(module, f) else { return };
+    // Only function-level replacements map to a Kotlin call; modules share one class
+    let replace_with = f.deprecated.as_ref()
+        .and_then(|d| d.replacement.as_ref())
+        .and_then(|r| module.functions.iter().find(|g| &g.name == r))
+        .map(|g| {
+            let args: Vec<&str> = g.params.iter().map(|p| p.name.as_str()).collect();
+            format!(", ReplaceWith(\"{}({})\")", g.name, args.join(", "))
+        })
+        .unwrap_or_default();
+    writeln!(out, "        @Deprecated(\"{}\"{})", escape_literal(&d.note()).replace('$', "\\$"), replace_with).ok();
+}
+
+/// JNI symbol form of a Java package: `.` becomes `_`, and `_` becomes `_1`.
+fn jni_mangle(package: &str) -> String {
+    package.replace('_', "_1").replace('.', "_")
+}
+
+/// Name of the `external` method (and thus the JNI symbol suffix) for `f`.
+fn jni_method_name(f: &Function) -> String {
+    if uses_typed_handles(f) { format!("{}Native", f.name) } else { f.name.clone() }
+}
+
 fn jni_param_type(t: &TypeRef) -> &'static str {
     match t {
         TypeRef::I32 | TypeRef::U32 => "jint",
-        TypeRef::I64 | TypeRef::Handle => "jlong",
+        TypeRef::I64 | TypeRef::Handle | TypeRef::TypedHandle(_) => "jlong",
         TypeRef::F64 => "jdouble",
         TypeRef::Bool => "jboolean",
         TypeRef::StringUtf8 => "jstring",
@@ -197,7 +320,7 @@ fn jni_ret_type(t: Option<&TypeRef>) -> &'static str {
     match t {
         None => "void",
         Some(TypeRef::I32 | TypeRef::U32) => "jint",
-        Some(TypeRef::I64 | TypeRef::Handle) => "jlong",
+        Some(TypeRef::I64 | TypeRef::Handle | TypeRef::TypedHandle(_)) => "jlong",
         Some(TypeRef::F64) => "jdouble",
         Some(TypeRef::Bool) => "jboolean",
         Some(TypeRef::StringUtf8) => "jstring",
@@ -205,11 +328,92 @@ fn jni_ret_type(t: Option<&TypeRef>) -> &'static str {
     }
 }
 
-fn write_error_throw(out: &mut String) {
+/// Borrowed byte arrays are pinned with `GetPrimitiveArrayCritical` (zero-copy on
+/// most VMs). No other JNI call may run inside a critical region, so those are
+/// acquired last and released first. JNI strings are UTF-16 internally, so
+/// string params are always converted (copied) regardless of ownership.
+fn is_critical(p: &Param) -> bool {
+    p.ty == TypeRef::Bytes && p.ownership == Ownership::Borrowed
+}
+
+fn write_param_acquires(out: &mut String, params: &[Param]) {
+    for p in params.iter().filter(|p| !is_critical(p)) {
+        match p.ty {
+            TypeRef::StringUtf8 => {
+                let _ = writeln!(out, "    const char* {n}_chars = (*env)->GetStringUTFChars(env, {n}, NULL);", n = p.name);
+                let _ = writeln!(out, "    jsize {n}_len = (*env)->GetStringUTFLength(env, {n});", n = p.name);
+            }
+            TypeRef::Bytes => {
+                let _ = writeln!(out, "    jboolean {n}_is_copy = 0;", n = p.name);
+                let _ = writeln!(out, "    jbyte* {n}_elems = (*env)->GetByteArrayElements(env, {n}, &{n}_is_copy);", n = p.name);
+                let _ = writeln!(out, "    jsize {n}_len = (*env)->GetArrayLength(env, {n});", n = p.name);
+            }
+            _ => {}
+        }
+    }
+    // No JNI calls are allowed inside a critical region, so every length
+    // is read before the first acquire
+    for p in params.iter().filter(|p| is_critical(p)) {
+        let _ = writeln!(out, "    jsize {n}_len = (*env)->GetArrayLength(env, {n});", n = p.name);
+    }
+    for p in params.iter().filter(|p| is_critical(p)) {
+        let _ = writeln!(out, "    jbyte* {n}_elems = (jbyte*)(*env)->GetPrimitiveArrayCritical(env, {n}, NULL);", n = p.name);
+    }
+}
+
+fn write_param_releases(out: &mut String, params: &[Param]) {
+    for p in params.iter().rev().filter(|p| is_critical(p)) {
+        // JNI_ABORT: the callee only reads, so nothing needs copying back
+        let _ = writeln!(out, "    (*env)->ReleasePrimitiveArrayCritical(env, {n}, {n}_elems, JNI_ABORT);", n = p.name);
+    }
+    for p in params.iter().filter(|p| !is_critical(p)) {
+        match p.ty {
+            TypeRef::StringUtf8 => {
+                let _ = writeln!(out, "    (*env)->ReleaseStringUTFChars(env, {n}, {n}_chars);", n = p.name);
+            }
+            TypeRef::Bytes => {
+                let _ = writeln!(out, "    (*env)->ReleaseByteArrayElements(env, {n}, {n}_elems, 0);", n = p.name);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Throw the call's error, if any, and return `fail` from the entry point.
+fn write_error_throw(out: &mut String, last_error: bool, fail: &str) {
+    if last_error {
+        let _ = writeln!(out, "    if (weaveffi_last_error_code() != 0) {{");
+        let _ = writeln!(out, "        const char* last_msg = weaveffi_last_error_message();");
+        let _ = writeln!(out, "        weaveffi_throw(env, weaveffi_last_error_code(), last_msg ? last_msg : \"WeaveFFI error\");");
+        let _ = writeln!(out, "        weaveffi_last_error_clear();");
+        let _ = writeln!(out, "        {}", fail);
+        let _ = writeln!(out, "    }}");
+        return;
+    }
     let _ = writeln!(out, "    if (err.code != 0) {{");
-    let _ = writeln!(out, "        jclass exClass = (*env)->FindClass(env, \"java/lang/RuntimeException\");");
-    let _ = writeln!(out, "        const char* msg = err.message ? err.message : \"WeaveFFI error\";");
-    let _ = writeln!(out, "        (*env)->ThrowNew(env, exClass, msg);");
+    let _ = writeln!(out, "        weaveffi_throw(env, err.code, err.message ? err.message : \"WeaveFFI error\");");
     let _ = writeln!(out, "        weaveffi_error_clear(&err);");
+    let _ = writeln!(out, "        {}", fail);
     let _ = writeln!(out, "    }}");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borrowed_bytes(name: &str) -> Param {
+        Param { name: name.into(), ty: TypeRef::Bytes, doc: None, ownership: Ownership::Borrowed }
+    }
+
+    #[test]
+    fn critical_regions_make_no_jni_calls() {
+        let params = [borrowed_bytes("a"), borrowed_bytes("b")];
+        let mut acquires = String::new();
+        write_param_acquires(&mut acquires, &params);
+        let first = acquires.find("GetPrimitiveArrayCritical").unwrap();
+        assert!(acquires.rfind("GetArrayLength").unwrap() < first);
+        let mut releases = String::new();
+        write_param_releases(&mut releases, &params);
+        assert!(releases.find("b_elems").unwrap() < releases.find("a_elems").unwrap());
+    }
+}
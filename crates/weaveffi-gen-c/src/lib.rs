@@ -1,22 +1,25 @@
 use anyhow::Result;
-use camino::Utf8Path;
 use tracing::info;
 use weaveffi_core::codegen::Generator;
-use weaveffi_core::templates::{render_c_convenience_c, render_c_header};
+use weaveffi_core::config::GeneratorOptions;
+use weaveffi_core::output::FileSet;
+use weaveffi_core::render::{Bundled, Renderer};
+use weaveffi_core::templates::{render_c_convenience_c, render_c_header, C_TEMPLATES};
 use weaveffi_ir::ir::Api;
 
 pub struct CGenerator;
 
 impl Generator for CGenerator {
-    fn name(&self) -> &'static str { "c-header" }
-    fn generate(&self, api: &Api, out_dir: &Utf8Path) -> Result<()> {
+    fn name(&self) -> &'static str { "c" }
+    fn templates(&self) -> Bundled { C_TEMPLATES }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
         info!("generating C header template");
-        let dir = out_dir.join("c");
-        std::fs::create_dir_all(&dir)?;
-        let header = render_c_header(api);
-        std::fs::write(dir.join("weaveffi.h"), header)?;
-        let c_shim = render_c_convenience_c();
-        std::fs::write(dir.join("weaveffi.c"), c_shim)?;
+        let dir = &options.c.dir;
+        let renderer = Renderer::new(C_TEMPLATES, options.templates.as_deref())?;
+        let header = render_c_header(api, &renderer)?;
+        files.add(dir.join("weaveffi.h"), header);
+        let c_shim = render_c_convenience_c(api, &renderer)?;
+        files.add(dir.join("weaveffi.c"), c_shim);
         Ok(())
     }
 }
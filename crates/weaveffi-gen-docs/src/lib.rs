@@ -0,0 +1,187 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use serde::Serialize;
+use tracing::info;
+use weaveffi_core::codegen::Generator;
+use weaveffi_core::config::GeneratorOptions;
+use weaveffi_core::output::FileSet;
+use weaveffi_core::compat::api_hash;
+use weaveffi_core::render::{Bundled, Renderer};
+use weaveffi_core::templates::{c_prototype, doc_lines, effective_deprecation, swift_signature, ts_signature};
+use weaveffi_gen_android::kotlin_signature;
+use weaveffi_ir::ir::{Api, Function, Module, TypeRef};
+
+/// mdBook-compatible API reference: one page per module, with every function's
+/// signature in each target language side by side.
+pub struct DocsGenerator;
+
+/// Templates of the docs generator.
+pub const DOCS_TEMPLATES: Bundled = &[
+    ("docs/book.toml.tera", include_str!("../templates/docs/book.toml.tera")),
+    ("docs/SUMMARY.md.tera", include_str!("../templates/docs/SUMMARY.md.tera")),
+    ("docs/README.md.tera", include_str!("../templates/docs/README.md.tera")),
+    ("docs/module.md.tera", include_str!("../templates/docs/module.md.tera")),
+];
+
+/// Context of `book.toml`, `SUMMARY.md` and `README.md`. Free text in every
+/// context is already safe inside a Markdown table cell.
+#[derive(Serialize)]
+struct BookContext<'a> {
+    version: &'a str,
+    /// 16 hex digits
+    api_hash: String,
+    error_convention: &'static str,
+    string_return: &'static str,
+    modules: Vec<ModuleSummary<'a>>,
+}
+
+#[derive(Serialize)]
+struct ModuleSummary<'a> {
+    name: &'a str,
+    /// Number of functions
+    functions: usize,
+    /// First doc line
+    summary: String,
+}
+
+/// Context of `module.md`, rendered once per module.
+#[derive(Serialize)]
+struct ModuleContext<'a> {
+    name: &'a str,
+    since: Option<&'a str>,
+    deprecated: Option<String>,
+    doc: Vec<&'a str>,
+    has_doc: bool,
+    handles: Vec<HandleRow<'a>>,
+    errors: Option<ErrorTable<'a>>,
+    functions: Vec<FunctionContext<'a>>,
+}
+
+#[derive(Serialize)]
+struct HandleRow<'a> {
+    name: &'a str,
+    /// Release function, or `-`
+    release: &'a str,
+}
+
+#[derive(Serialize)]
+struct ErrorTable<'a> {
+    name: &'a str,
+    codes: Vec<ErrorRow<'a>>,
+}
+
+#[derive(Serialize)]
+struct ErrorRow<'a> {
+    name: &'a str,
+    code: i32,
+    message: String,
+    doc: String,
+}
+
+#[derive(Serialize)]
+struct FunctionContext<'a> {
+    name: &'a str,
+    since: Option<&'a str>,
+    /// Own or inherited from the module
+    deprecated: Option<String>,
+    doc: Vec<&'a str>,
+    has_doc: bool,
+    params: Vec<ParamRow<'a>>,
+    /// Sentence describing the return value
+    returns: String,
+    c: String,
+    swift: String,
+    kotlin: String,
+    typescript: String,
+}
+
+#[derive(Serialize)]
+struct ParamRow<'a> {
+    name: &'a str,
+    ty: String,
+    /// Only meaningful for strings and bytes; `-` otherwise
+    ownership: &'static str,
+    doc: String,
+}
+
+impl Generator for DocsGenerator {
+    fn name(&self) -> &'static str { "docs" }
+    fn templates(&self) -> Bundled { DOCS_TEMPLATES }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
+        info!("generating API reference (mdBook)");
+        let renderer = Renderer::new(DOCS_TEMPLATES, options.templates.as_deref())?;
+        let src = Utf8Path::new("src");
+        let book = BookContext {
+            version: &api.version,
+            api_hash: format!("{:016x}", api_hash(api)),
+            error_convention: api.error_convention.as_str(),
+            string_return: api.string_return.as_str(),
+            modules: api.modules.iter().map(|m| ModuleSummary {
+                name: &m.name,
+                functions: m.functions.len(),
+                summary: cell(doc_lines(m.doc.as_deref()).first().copied().unwrap_or("")),
+            }).collect(),
+        };
+        files.add("book.toml", renderer.render("docs/book.toml.tera", &book)?);
+        for m in &api.modules {
+            files.add(src.join(format!("{}.md", m.name)), renderer.render("docs/module.md.tera", &module_context(api, m))?);
+        }
+        files.add(src.join("SUMMARY.md"), renderer.render("docs/SUMMARY.md.tera", &book)?);
+        files.add(src.join("README.md"), renderer.render("docs/README.md.tera", &book)?);
+        Ok(())
+    }
+}
+
+fn module_context<'a>(api: &Api, m: &'a Module) -> ModuleContext<'a> {
+    ModuleContext {
+        name: &m.name,
+        since: m.since.as_deref(),
+        deprecated: m.deprecated.as_ref().map(|d| d.note()),
+        doc: doc_lines(m.doc.as_deref()),
+        has_doc: m.doc.is_some(),
+        handles: m.handles.iter().map(|h| HandleRow { name: &h.name, release: h.release.as_deref().unwrap_or("-") }).collect(),
+        errors: m.errors.as_ref().filter(|e| !e.codes.is_empty()).map(|errors| ErrorTable {
+            name: &errors.name,
+            codes: errors.codes.iter().map(|c| ErrorRow {
+                name: &c.name,
+                code: c.code,
+                message: cell(&c.message),
+                doc: cell(&doc_lines(c.doc.as_deref()).join(" ")),
+            }).collect(),
+        }),
+        functions: m.functions.iter().map(|f| function_context(api, m, f)).collect(),
+    }
+}
+
+fn function_context<'a>(api: &Api, m: &'a Module, f: &'a Function) -> FunctionContext<'a> {
+    let returns = match &f.returns {
+        Some(ret) if is_buffer(ret) => format!("Returns `{}` ({}).", ret, f.return_ownership.as_str()),
+        Some(ret) => format!("Returns `{}`.", ret),
+        None => "Returns nothing.".to_string(),
+    };
+    FunctionContext {
+        name: &f.name,
+        since: f.since.as_deref(),
+        deprecated: effective_deprecation(m, f).map(|d| d.note()),
+        doc: doc_lines(f.doc.as_deref()),
+        has_doc: f.doc.is_some(),
+        params: f.params.iter().map(|p| ParamRow {
+            name: &p.name,
+            ty: p.ty.to_string(),
+            ownership: if is_buffer(&p.ty) { p.ownership.as_str() } else { "-" },
+            doc: cell(&doc_lines(p.doc.as_deref()).join(" ")),
+        }).collect(),
+        returns,
+        c: c_prototype(m, f, api),
+        swift: swift_signature(f),
+        kotlin: kotlin_signature(f),
+        typescript: ts_signature(f),
+    }
+}
+
+fn is_buffer(t: &TypeRef) -> bool { matches!(t, TypeRef::StringUtf8 | TypeRef::Bytes) }
+
+/// Keep free text from breaking a Markdown table row.
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
@@ -1,22 +1,41 @@
-use anyhow::Result;
-use camino::Utf8Path;
+use anyhow::{bail, Result};
+use serde::Serialize;
 use tracing::info;
 use weaveffi_core::codegen::Generator;
-use weaveffi_ir::ir::Api;
-use weaveffi_core::templates::{render_node_dts};
+use weaveffi_core::config::GeneratorOptions;
+use weaveffi_core::output::FileSet;
+use weaveffi_core::render::{Bundled, Renderer};
+use weaveffi_core::templates::{render_node_dts, CompatContext, NODE_TEMPLATES};
+use weaveffi_ir::ir::{Api, ErrorConvention};
 
 pub struct NodeGenerator;
 
+/// Context of `node/package.json.tera`.
+#[derive(Serialize)]
+struct PackageContext<'a> {
+    package: &'a str,
+    version: &'a str,
+}
+
 impl Generator for NodeGenerator {
     fn name(&self) -> &'static str { "node" }
-    fn generate(&self, api: &Api, out_dir: &Utf8Path) -> Result<()> {
+    fn templates(&self) -> Bundled { NODE_TEMPLATES }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
         info!("generating Node.js N-API loader and types");
-        let dir = out_dir.join("node");
-        std::fs::create_dir_all(&dir)?;
-        // Simple loader that expects a compiled addon next to it
-        std::fs::write(dir.join("index.js"), "module.exports = require('./index.node')\n")?;
-        std::fs::write(dir.join("types.d.ts"), render_node_dts(api))?;
-        std::fs::write(dir.join("package.json"), "{\n  \"name\": \"weaveffi\",\n  \"version\": \"0.1.0\",\n  \"main\": \"index.js\",\n  \"types\": \"types.d.ts\"\n}\n")?;
+        // The loader re-exports the addon as is, and the addon reads `out_err`
+        if api.error_convention == ErrorConvention::LastError {
+            bail!("the node target does not support `error_convention: last_error`; exclude it with `--exclude node`");
+        }
+        let renderer = Renderer::new(NODE_TEMPLATES, options.templates.as_deref())?;
+        let dir = &options.node.dir;
+        // Loads the compiled addon next to it and refuses a mismatched library
+        files.add(dir.join("index.js"), renderer.render("node/index.js.tera", &CompatContext::new(api))?);
+        files.add(dir.join("types.d.ts"), render_node_dts(api, &renderer)?);
+        let package = PackageContext {
+            package: &options.node.package,
+            version: options.node.version.as_deref().unwrap_or(&api.version),
+        };
+        files.add(dir.join("package.json"), renderer.render("node/package.json.tera", &package)?);
         Ok(())
     }
 }
@@ -1,47 +1,46 @@
 use anyhow::Result;
-use camino::Utf8Path;
+use serde::Serialize;
 use tracing::info;
 use weaveffi_core::codegen::Generator;
-use weaveffi_core::templates::render_swift_wrapper;
+use weaveffi_core::config::{relative_path, GeneratorOptions};
+use weaveffi_core::output::FileSet;
+use weaveffi_core::render::{Bundled, Renderer};
+use weaveffi_core::templates::{render_swift_wrapper, SWIFT_TEMPLATES};
 use weaveffi_ir::ir::Api;
 
 pub struct SwiftGenerator;
 
+/// Context of `swift/Package.swift.tera` and `swift/module.modulemap.tera`.
+#[derive(Serialize)]
+struct PackageContext<'a> {
+    module: &'a str,
+    /// C header, relative to the module map
+    header: String,
+    library: &'a str,
+}
+
 impl Generator for SwiftGenerator {
     fn name(&self) -> &'static str { "swift" }
-    fn generate(&self, _api: &Api, out_dir: &Utf8Path) -> Result<()> {
+    // The module map includes the C header
+    fn dependencies(&self) -> Vec<&str> { vec!["c"] }
+    fn templates(&self) -> Bundled { SWIFT_TEMPLATES }
+    fn generate(&self, api: &Api, options: &GeneratorOptions, files: &mut FileSet) -> Result<()> {
         info!("generating SwiftPM System Library template");
-        let dir = out_dir.join("swift");
-        let module_dir = dir.join("WeaveFFI");
-        std::fs::create_dir_all(&module_dir)?;
-        // Package.swift
-        let package = r#"// swift-tools-version:5.7
-import PackageDescription
-
-let package = Package(
-    name: \"WeaveFFI\",
-    products: [
-        .library(name: \"WeaveFFI\", targets: [\"WeaveFFI\"]),
-    ],
-    targets: [
-        .systemLibrary(name: \"WeaveFFI\", pkgConfig: nil)
-    ]
-)
-"#;
-        std::fs::write(dir.join("Package.swift"), package)?;
-        // module.modulemap
-        let modulemap = r#"module WeaveFFI [system] {
-  header \"../../c/weaveffi.h\"
-  link \"weaveffi\"
-  export *
-}
-"#;
-        std::fs::write(module_dir.join("module.modulemap"), modulemap)?;
+        let renderer = Renderer::new(SWIFT_TEMPLATES, options.templates.as_deref())?;
+        let name = &options.swift.module;
+        let dir = &options.swift.dir;
+        let module_dir = dir.join(name);
+        let package = PackageContext {
+            module: name,
+            header: relative_path(&options.swift.dir.join(name), &options.c.dir.join("weaveffi.h")).into_string(),
+            library: options.swift_library(),
+        };
+        files.add(dir.join("Package.swift"), renderer.render("swift/Package.swift.tera", &package)?);
+        files.add(module_dir.join("module.modulemap"), renderer.render("swift/module.modulemap.tera", &package)?);
         // Thin Swift wrapper generated from IR
-        let swift_wrapper = render_swift_wrapper(_api);
-        let src_dir = dir.join("Sources").join("WeaveFFI");
-        std::fs::create_dir_all(&src_dir)?;
-        std::fs::write(src_dir.join("WeaveFFI.swift"), swift_wrapper)?;
+        let swift_wrapper = render_swift_wrapper(api, name, &renderer)?;
+        let src_dir = dir.join("Sources").join(name);
+        files.add(src_dir.join(format!("{}.swift", name)), swift_wrapper);
         Ok(())
     }
 }
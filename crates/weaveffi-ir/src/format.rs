@@ -0,0 +1,641 @@
+//! Canonical formatting of IDL files and lossless conversion between formats.
+//!
+//! Output uses a fixed key order, one inline mapping per param, handle and
+//! error code, and quotes strings only when the format requires it (versions
+//! are always quoted). `#` comments are carried over to YAML and TOML output,
+//! attached to the next declared item; JSON has no comments, so they are dropped.
+
+use crate::ir::{Api, Deprecation, ErrorCode, ErrorDomain, Function, HandleType, Module, Ownership, Param};
+use crate::parse::{parse_api_str, ParseError};
+use crate::source::find_declaration;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("formatted output does not round-trip to the same API; please report this as a bug")]
+    NotLossless,
+}
+
+/// A formatted IDL document.
+pub struct Formatted {
+    pub text: String,
+    /// Comments that could not be carried over (JSON output)
+    pub dropped_comments: usize,
+}
+
+/// Reformat `src` (in format `from`) canonically as format `to`.
+pub fn format_api_str(src: &str, from: &str, to: &str) -> Result<Formatted, FormatError> {
+    let api = parse_api_str(src, from)?;
+    let mut doc = api_table(&api);
+    let comments = if matches!(from, "json" | "weave" | "wit") { Comments::default() } else { attach_comments(src, &mut doc) };
+    let formatted = render(&doc, &comments, to)?;
+    check_lossless(&api, &formatted.text, to)?;
+    Ok(formatted)
+}
+
+/// Render `api` canonically as format `to`, for APIs that were not read from an IDL file.
+pub fn render_api(api: &Api, to: &str) -> Result<String, FormatError> {
+    let text = render(&api_table(api), &Comments::default(), to)?.text;
+    check_lossless(api, &text, to)?;
+    Ok(text)
+}
+
+fn render(doc: &Table, comments: &Comments, to: &str) -> Result<Formatted, FormatError> {
+    let (text, dropped_comments) = match to {
+        "yaml" | "yml" => (render_yaml(doc, comments), 0),
+        "toml" => (render_toml(doc, comments), 0),
+        "json" => (render_json(doc), comments.count()),
+        other => return Err(ParseError::UnsupportedFormat(other.to_string()).into()),
+    };
+    Ok(Formatted { text, dropped_comments })
+}
+
+fn check_lossless(api: &Api, text: &str, to: &str) -> Result<(), FormatError> {
+    let reparsed = parse_api_str(text, to)?;
+    if serde_json::to_value(api).ok() != serde_json::to_value(&reparsed).ok() {
+        return Err(FormatError::NotLossless);
+    }
+    Ok(())
+}
+
+enum Value {
+    Str(String),
+    /// Always quoted, so "1.0" never reads as a number
+    Version(String),
+    Int(i64),
+    Bool(bool),
+    Table(Table),
+    List(Vec<Value>),
+}
+
+/// A mapping in canonical key order; inline tables render on one line.
+#[derive(Default)]
+struct Table {
+    entries: Vec<(&'static str, Value)>,
+    inline: bool,
+    /// Names from the root to this item, for locating it in the source
+    path: Vec<String>,
+    comments: Vec<String>,
+}
+
+impl Table {
+    fn new(inline: bool, path: Vec<String>) -> Self {
+        Self { inline, path, ..Default::default() }
+    }
+
+    fn push(&mut self, key: &'static str, value: Value) {
+        self.entries.push((key, value));
+    }
+
+    /// Nested tables, including those inside lists.
+    fn children(&self) -> impl Iterator<Item = &Table> {
+        self.entries.iter().flat_map(|(_, v)| match v {
+            Value::Table(t) => std::slice::from_ref(t).iter().collect::<Vec<_>>(),
+            Value::List(items) => items.iter().filter_map(|v| if let Value::Table(t) = v { Some(t) } else { None }).collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    fn opt(&mut self, key: &'static str, value: Option<&String>, wrap: fn(String) -> Value) {
+        if let Some(v) = value {
+            self.push(key, wrap(v.clone()));
+        }
+    }
+}
+
+/// Snake-case name of a unit enum variant, as serialized.
+fn variant<T: Serialize>(v: &T) -> String {
+    serde_json::to_value(v).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+fn child(path: &[String], name: &str) -> Vec<String> {
+    path.iter().cloned().chain([name.to_string()]).collect()
+}
+
+fn api_table(api: &Api) -> Table {
+    let mut t = Table::new(false, Vec::new());
+    t.push("version", Value::Version(api.version.clone()));
+    if api.error_convention != Default::default() {
+        t.push("error_convention", Value::Str(variant(&api.error_convention)));
+    }
+    if api.string_return != Default::default() {
+        t.push("string_return", Value::Str(variant(&api.string_return)));
+    }
+    t.push("modules", Value::List(api.modules.iter().map(|m| Value::Table(module_table(m))).collect()));
+    t
+}
+
+fn module_table(m: &Module) -> Table {
+    let path = vec![m.name.clone()];
+    let mut t = Table::new(false, path.clone());
+    t.push("name", Value::Str(m.name.clone()));
+    t.opt("doc", m.doc.as_ref(), Value::Str);
+    t.opt("since", m.since.as_ref(), Value::Version);
+    if let Some(d) = &m.deprecated {
+        t.push("deprecated", Value::Table(deprecation_table(d)));
+    }
+    if !m.handles.is_empty() {
+        t.push("handles", Value::List(m.handles.iter().map(|h| Value::Table(handle_table(&path, h))).collect()));
+    }
+    if let Some(errors) = &m.errors {
+        t.push("errors", Value::Table(errors_table(&path, errors)));
+    }
+    t.push("functions", Value::List(m.functions.iter().map(|f| Value::Table(function_table(&path, f))).collect()));
+    t
+}
+
+fn deprecation_table(d: &Deprecation) -> Table {
+    let mut t = Table::new(true, Vec::new());
+    t.opt("since", d.since.as_ref(), Value::Version);
+    t.opt("message", d.message.as_ref(), Value::Str);
+    t.opt("replacement", d.replacement.as_ref(), Value::Str);
+    t
+}
+
+fn handle_table(path: &[String], h: &HandleType) -> Table {
+    let mut t = Table::new(true, child(path, &h.name));
+    t.push("name", Value::Str(h.name.clone()));
+    t.opt("release", h.release.as_ref(), Value::Str);
+    t
+}
+
+fn errors_table(path: &[String], errors: &ErrorDomain) -> Table {
+    let path = child(path, &errors.name);
+    let mut t = Table::new(false, path.clone());
+    t.push("name", Value::Str(errors.name.clone()));
+    t.push("codes", Value::List(errors.codes.iter().map(|c| Value::Table(code_table(&path, c))).collect()));
+    t
+}
+
+fn code_table(path: &[String], c: &ErrorCode) -> Table {
+    let mut t = Table::new(true, child(path, &c.name));
+    t.push("name", Value::Str(c.name.clone()));
+    t.push("code", Value::Int(c.code.into()));
+    t.push("message", Value::Str(c.message.clone()));
+    t.opt("doc", c.doc.as_ref(), Value::Str);
+    t
+}
+
+fn function_table(path: &[String], f: &Function) -> Table {
+    let path = child(path, &f.name);
+    let mut t = Table::new(false, path.clone());
+    t.push("name", Value::Str(f.name.clone()));
+    t.opt("doc", f.doc.as_ref(), Value::Str);
+    t.opt("since", f.since.as_ref(), Value::Version);
+    if let Some(d) = &f.deprecated {
+        t.push("deprecated", Value::Table(deprecation_table(d)));
+    }
+    if f.r#async {
+        t.push("async", Value::Bool(true));
+    }
+    t.push("params", Value::List(f.params.iter().map(|p| Value::Table(param_table(&path, p))).collect()));
+    if let Some(ret) = &f.returns {
+        t.push("return", Value::Str(ret.to_string()));
+    }
+    if f.return_ownership != Ownership::default() {
+        t.push("return_ownership", Value::Str(variant(&f.return_ownership)));
+    }
+    t
+}
+
+fn param_table(path: &[String], p: &Param) -> Table {
+    let mut t = Table::new(true, child(path, &p.name));
+    t.push("name", Value::Str(p.name.clone()));
+    t.push("type", Value::Str(p.ty.to_string()));
+    if p.ownership != Ownership::default() {
+        t.push("ownership", Value::Str(variant(&p.ownership)));
+    }
+    t.opt("doc", p.doc.as_ref(), Value::Str);
+    t
+}
+
+/// Comments that belong to the file rather than to an item.
+#[derive(Default)]
+struct Comments {
+    header: Vec<String>,
+    trailer: Vec<String>,
+    attached: usize,
+}
+
+impl Comments {
+    fn count(&self) -> usize {
+        self.header.len() + self.trailer.len() + self.attached
+    }
+}
+
+/// Attach each source comment to the first item declared at or after it.
+/// Comments before any content form the file header; those after the last item, the trailer.
+fn attach_comments(src: &str, doc: &mut Table) -> Comments {
+    let mut found = scan_comments(src);
+    let mut out = Comments::default();
+    let first_content = src
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim()))
+        })
+        .find(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map_or(src.len(), |(start, _)| start);
+    let header_len = found.iter().take_while(|(offset, _)| *offset < first_content).count();
+    out.header = found.drain(..header_len).map(|(_, text)| text).collect();
+
+    let mut items = Vec::new();
+    item_offsets(src, doc, 0, &mut items);
+    items.sort_by_key(|(offset, _)| *offset);
+    let mut by_path: HashMap<Vec<String>, Vec<String>> = HashMap::new();
+    for (line_start, text) in found {
+        match items.iter().find(|(offset, _)| *offset >= line_start) {
+            Some((_, path)) => {
+                by_path.entry(path.clone()).or_default().push(text);
+                out.attached += 1;
+            }
+            None => out.trailer.push(text),
+        }
+    }
+    assign_comments(doc, &mut by_path);
+    out
+}
+
+/// Source offset of every named item, searching each name after its parent's.
+fn item_offsets(src: &str, table: &Table, parent: usize, out: &mut Vec<(usize, Vec<String>)>) {
+    let mut from = parent;
+    if let Some(name) = table.path.last() {
+        let Some(span) = find_declaration(src, name, parent) else { return };
+        from = span.start;
+        out.push((from, table.path.clone()));
+    }
+    for child in table.children() {
+        item_offsets(src, child, from, out);
+    }
+}
+
+fn assign_comments(table: &mut Table, by_path: &mut HashMap<Vec<String>, Vec<String>>) {
+    if let Some(comments) = by_path.remove(&table.path) {
+        table.comments = comments;
+    }
+    for (_, value) in &mut table.entries {
+        match value {
+            Value::Table(t) => assign_comments(t, by_path),
+            Value::List(items) => {
+                for item in items {
+                    if let Value::Table(t) = item {
+                        assign_comments(t, by_path);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `#` comments as (offset of their line, text), skipping `#` inside strings,
+/// YAML block scalars and TOML multi-line strings.
+fn scan_comments(src: &str) -> Vec<(usize, String)> {
+    let mut out = Vec::new();
+    let mut block_indent: Option<usize> = None;
+    let mut triple: Option<&str> = None;
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let line = line.trim_end_matches(['\n', '\r']);
+        let indent = line.len() - line.trim_start().len();
+        if let Some(parent) = block_indent {
+            if line.trim().is_empty() || indent > parent {
+                continue;
+            }
+            block_indent = None;
+        }
+        let mut pos = 0;
+        if let Some(delim) = triple {
+            match line.find(delim) {
+                Some(end) => {
+                    triple = None;
+                    pos = end + delim.len();
+                }
+                None => continue,
+            }
+        }
+        let mut quote: Option<char> = None;
+        let mut content_end = line.len();
+        let bytes = line.as_bytes();
+        while pos < line.len() {
+            let rest = &line[pos..];
+            let c = rest.chars().next().unwrap_or_default();
+            match quote {
+                Some('"') if c == '\\' => pos += rest[1..].chars().next().map_or(0, char::len_utf8),
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None => {
+                    if let Some(delim) = ["\"\"\"", "'''"].into_iter().find(|d| rest.starts_with(d)) {
+                        match rest[3..].find(delim) {
+                            Some(end) => pos += 3 + end + 3,
+                            None => {
+                                triple = Some(delim);
+                                content_end = pos;
+                                break;
+                            }
+                        }
+                        continue;
+                    }
+                    // Quotes only open a string at the start of a scalar, not in `it's`
+                    let opens = line[..pos].trim_end().chars().next_back().is_none_or(|p| ":,[{-=".contains(p));
+                    if (c == '"' || c == '\'') && opens {
+                        quote = Some(c);
+                    } else if c == '#' && (pos == 0 || bytes[pos - 1] == b' ' || bytes[pos - 1] == b'\t') {
+                        out.push((start, line[pos..].trim_end().to_string()));
+                        content_end = pos;
+                        break;
+                    }
+                }
+            }
+            pos += c.len_utf8();
+        }
+        let content = line[..content_end].trim_end();
+        let indicator = content.trim_end_matches(|c: char| c == '-' || c == '+' || c.is_ascii_digit());
+        if triple.is_none() && (indicator.ends_with(" |") || indicator.ends_with(" >")) {
+            block_indent = Some(indent);
+        }
+    }
+    out
+}
+
+/// YAML scalar, plain where unambiguous, double-quoted otherwise.
+fn yaml_str(s: &str, flow: bool) -> String {
+    const SPECIAL: &[&str] = &["true", "false", "yes", "no", "on", "off", "null", "y", "n", "~"];
+    let reserved = SPECIAL.contains(&s.to_ascii_lowercase().as_str()) || s.parse::<f64>().is_ok();
+    let indicator = s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c) || c.is_whitespace());
+    let unsafe_char = s.contains(": ") || s.contains(" #") || s.ends_with(':') || s.ends_with(char::is_whitespace)
+        || s.contains(|c: char| c.is_control())
+        || (flow && s.contains(|c| ",[]{}".contains(c)));
+    if !s.is_empty() && !reserved && !indicator && !unsafe_char {
+        s.to_string()
+    } else {
+        json_str(s)
+    }
+}
+
+fn json_str(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn yaml_scalar(v: &Value, flow: bool) -> String {
+    match v {
+        Value::Str(s) => yaml_str(s, flow),
+        Value::Version(s) => json_str(s),
+        Value::Int(i) => i.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Table(t) => yaml_flow(t),
+        Value::List(items) => format!("[{}]", items.iter().map(|v| yaml_scalar(v, true)).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+fn yaml_flow(t: &Table) -> String {
+    if t.entries.is_empty() {
+        return "{}".into();
+    }
+    let entries: Vec<String> = t.entries.iter().map(|(k, v)| format!("{}: {}", k, yaml_scalar(v, true))).collect();
+    format!("{{ {} }}", entries.join(", "))
+}
+
+/// `|` block scalar for multi-line text whose layout YAML can reproduce exactly.
+fn yaml_block(s: &str, indent: usize) -> Option<String> {
+    let body = s.strip_suffix('\n').unwrap_or(s);
+    let representable = s.contains('\n')
+        && !body.ends_with('\n')
+        && !body.starts_with([' ', '\n'])
+        && !s.contains(|c: char| c.is_control() && c != '\n');
+    if !representable {
+        return None;
+    }
+    let mut out = String::from(if s.ends_with('\n') { "|" } else { "|-" });
+    for line in body.split('\n') {
+        if line.is_empty() { out.push('\n'); } else { write!(out, "\n{:indent$}{}", "", line, indent = indent).ok(); }
+    }
+    Some(out)
+}
+
+fn push_comments(out: &mut String, comments: &[String], indent: usize) {
+    for c in comments {
+        writeln!(out, "{:indent$}{}", "", c, indent = indent).ok();
+    }
+}
+
+fn render_yaml(doc: &Table, comments: &Comments) -> String {
+    let mut out = String::new();
+    push_comments(&mut out, &comments.header, 0);
+    yaml_entries(&mut out, doc, 0, None);
+    push_comments(&mut out, &comments.trailer, 0);
+    out
+}
+
+/// Block mapping at `indent`; `first_prefix` replaces the indentation of the
+/// first line (for `- ` list items).
+fn yaml_entries(out: &mut String, t: &Table, indent: usize, first_prefix: Option<&str>) {
+    for (i, (key, value)) in t.entries.iter().enumerate() {
+        if let Value::Table(child) = value {
+            push_comments(out, &child.comments, indent);
+        }
+        match (i, first_prefix) {
+            (0, Some(prefix)) => out.push_str(prefix),
+            _ => out.push_str(&" ".repeat(indent)),
+        }
+        match value {
+            Value::Table(child) if !child.inline => {
+                out.push_str(key);
+                out.push_str(":\n");
+                yaml_entries(out, child, indent + 2, None);
+            }
+            Value::List(items) if !items.is_empty() => {
+                writeln!(out, "{}:", key).ok();
+                for item in items {
+                    match item {
+                        Value::Table(child) => {
+                            push_comments(out, &child.comments, indent + 2);
+                            if child.inline {
+                                writeln!(out, "{:indent$}- {}", "", yaml_flow(child), indent = indent + 2).ok();
+                            } else {
+                                let prefix = format!("{:indent$}- ", "", indent = indent + 2);
+                                yaml_entries(out, child, indent + 4, Some(&prefix));
+                            }
+                        }
+                        other => {
+                            writeln!(out, "{:indent$}- {}", "", yaml_scalar(other, false), indent = indent + 2).ok();
+                        }
+                    }
+                }
+            }
+            Value::Str(s) => {
+                let text = yaml_block(s, indent + 2).unwrap_or_else(|| yaml_str(s, false));
+                writeln!(out, "{}: {}", key, text).ok();
+            }
+            other => {
+                writeln!(out, "{}: {}", key, yaml_scalar(other, false)).ok();
+            }
+        }
+    }
+}
+
+fn render_json(doc: &Table) -> String {
+    let mut out = String::new();
+    json_table(&mut out, doc, 0);
+    out.push('\n');
+    out
+}
+
+fn json_value(out: &mut String, v: &Value, indent: usize) {
+    match v {
+        Value::Str(s) | Value::Version(s) => out.push_str(&json_str(s)),
+        Value::Int(i) => {
+            write!(out, "{}", i).ok();
+        }
+        Value::Bool(b) => {
+            write!(out, "{}", b).ok();
+        }
+        Value::Table(t) => json_table(out, t, indent),
+        Value::List(items) if items.is_empty() => out.push_str("[]"),
+        Value::List(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                write!(out, "{:indent$}", "", indent = indent + 2).ok();
+                json_value(out, item, indent + 2);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            write!(out, "{:indent$}]", "", indent = indent).ok();
+        }
+    }
+}
+
+fn json_table(out: &mut String, t: &Table, indent: usize) {
+    if t.entries.is_empty() {
+        out.push_str("{}");
+    } else if t.inline {
+        out.push_str("{ ");
+        for (i, (key, value)) in t.entries.iter().enumerate() {
+            if i > 0 { out.push_str(", "); }
+            write!(out, "{}: ", json_str(key)).ok();
+            json_value(out, value, indent);
+        }
+        out.push_str(" }");
+    } else {
+        out.push_str("{\n");
+        for (i, (key, value)) in t.entries.iter().enumerate() {
+            write!(out, "{:indent$}{}: ", "", json_str(key), indent = indent + 2).ok();
+            json_value(out, value, indent + 2);
+            out.push_str(if i + 1 < t.entries.len() { ",\n" } else { "\n" });
+        }
+        write!(out, "{:indent$}}}", "", indent = indent).ok();
+    }
+}
+
+fn toml_str(s: &str) -> String {
+    format!("\"{}\"", toml_escape(s, false))
+}
+
+/// `"""` string for multi-line text; the newline after the opening quotes is not part of the value.
+fn toml_multiline(s: &str) -> String {
+    format!("\"\"\"\n{}\"\"\"", toml_escape(s, true))
+}
+
+fn toml_escape(s: &str, multiline: bool) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' if multiline => out.push('\n'),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => {
+                write!(out, "\\u{:04X}", c as u32).ok();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn toml_scalar(v: &Value) -> String {
+    match v {
+        Value::Str(s) | Value::Version(s) => toml_str(s),
+        Value::Int(i) => i.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Table(t) => {
+            let entries: Vec<String> = t.entries.iter().map(|(k, v)| format!("{} = {}", k, toml_scalar(v))).collect();
+            if entries.is_empty() { "{}".into() } else { format!("{{ {} }}", entries.join(", ")) }
+        }
+        Value::List(items) => format!("[{}]", items.iter().map(toml_scalar).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+fn is_block_table(v: &Value) -> bool {
+    matches!(v, Value::Table(t) if !t.inline)
+}
+
+fn is_table_array(v: &Value) -> bool {
+    matches!(v, Value::List(items) if items.first().is_some_and(is_block_table))
+}
+
+fn render_toml(doc: &Table, comments: &Comments) -> String {
+    let mut out = String::new();
+    push_comments(&mut out, &comments.header, 0);
+    toml_table(&mut out, doc, "");
+    if !comments.trailer.is_empty() {
+        out.push('\n');
+        push_comments(&mut out, &comments.trailer, 0);
+    }
+    out
+}
+
+/// Key/value pairs first, then sub-tables and arrays of tables, as TOML requires.
+fn toml_table(out: &mut String, t: &Table, path: &str) {
+    for (key, value) in &t.entries {
+        match value {
+            v if is_block_table(v) || is_table_array(v) => {}
+            Value::Str(s) if s.contains('\n') => {
+                writeln!(out, "{} = {}", key, toml_multiline(s)).ok();
+            }
+            Value::List(items) if items.iter().any(|v| matches!(v, Value::Table(_))) => {
+                writeln!(out, "{} = [", key).ok();
+                for item in items {
+                    if let Value::Table(child) = item {
+                        push_comments(out, &child.comments, 2);
+                    }
+                    writeln!(out, "  {},", toml_scalar(item)).ok();
+                }
+                out.push_str("]\n");
+            }
+            v => {
+                writeln!(out, "{} = {}", key, toml_scalar(v)).ok();
+            }
+        }
+    }
+    for (key, value) in &t.entries {
+        let child_path = if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+        match value {
+            Value::Table(child) if !child.inline => {
+                out.push('\n');
+                push_comments(out, &child.comments, 0);
+                writeln!(out, "[{}]", child_path).ok();
+                toml_table(out, child, &child_path);
+            }
+            Value::List(items) if is_table_array(value) => {
+                for item in items {
+                    if let Value::Table(child) = item {
+                        out.push('\n');
+                        push_comments(out, &child.comments, 0);
+                        writeln!(out, "[[{}]]", child_path).ok();
+                        toml_table(out, child, &child_path);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,60 +1,283 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Api {
     pub version: String,
     pub modules: Vec<Module>,
+    /// How functions report errors across the C ABI
+    #[serde(default)]
+    pub error_convention: ErrorConvention,
+    /// How `string` return values cross the C ABI
+    #[serde(default)]
+    pub string_return: StringReturn,
+}
+
+/// C ABI shape of returned strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StringReturn {
+    /// NUL-terminated `const char*`; interior NULs are stripped (default)
+    #[default]
+    NulTerminated,
+    /// `const char*` plus a `size_t* out_len` param, like `bytes`; preserves
+    /// interior NULs and spares callers a `strlen`. Free with `weaveffi_free_string_len`
+    OutLen,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl StringReturn {
+    /// Spelling in the IDL.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StringReturn::NulTerminated => "nul_terminated",
+            StringReturn::OutLen => "out_len",
+        }
+    }
+}
+
+/// Error reporting convention used by every generated C function.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorConvention {
+    /// Trailing `weaveffi_error* out_err` parameter (default)
+    #[default]
+    OutParam,
+    /// errno-style: no `out_err`; callers query `weaveffi_last_error_code()`
+    /// and `weaveffi_last_error_message()` on the calling thread
+    LastError,
+}
+
+impl ErrorConvention {
+    /// Spelling in the IDL.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorConvention::OutParam => "out_param",
+            ErrorConvention::LastError => "last_error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Module {
     pub name: String,
+    #[serde(default)]
+    pub doc: Option<String>,
     pub functions: Vec<Function>,
     /// Optional error domain for this module
     #[serde(default)]
     pub errors: Option<ErrorDomain>,
+    /// Typed handles owned by this module, referenced as `handle<Name>`
+    #[serde(default)]
+    pub handles: Vec<HandleType>,
+    /// API version that introduced this module
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Applies to every function in the module
+    #[serde(default)]
+    pub deprecated: Option<Deprecation>,
+}
+
+/// Deprecation notice carried into every generated binding.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Deprecation {
+    /// API version that deprecated the item
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Function in the same module to call instead
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+impl Deprecation {
+    /// One-line human-readable notice, e.g.
+    /// "Overflows silently (deprecated since 0.3.0; use add_checked instead)".
+    pub fn note(&self) -> String {
+        let mut details = Vec::new();
+        if let Some(since) = &self.since {
+            details.push(format!("deprecated since {}", since));
+        }
+        if let Some(replacement) = &self.replacement {
+            details.push(format!("use {} instead", replacement));
+        }
+        match (&self.message, details.is_empty()) {
+            (Some(msg), true) => msg.clone(),
+            (Some(msg), false) => format!("{} ({})", msg, details.join("; ")),
+            (None, true) => "deprecated".into(),
+            (None, false) => details.join("; "),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HandleType {
+    /// Type name, e.g. "Session" for `handle<Session>`
+    pub name: String,
+    /// Function in this module that releases the handle
+    #[serde(default)]
+    pub release: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Function {
     pub name: String,
     pub params: Vec<Param>,
     /// Use key "return" in serialized formats
     #[serde(rename = "return")]
+    #[schemars(description = "Return type; omit for functions that return nothing")]
     pub returns: Option<TypeRef>,
+    /// Who owns a returned `string`/`bytes` buffer
+    #[serde(default)]
+    pub return_ownership: Ownership,
     #[serde(default)]
     pub doc: Option<String>,
+    /// API version that introduced this function
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub deprecated: Option<Deprecation>,
     /// Async not supported for 0.1.0; present for forward-compat
     #[serde(default, rename = "async")]
     pub r#async: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Param {
     pub name: String,
     #[serde(rename = "type")]
     pub ty: TypeRef,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// How a `string`/`bytes` argument is handed to the callee
+    #[serde(default)]
+    pub ownership: Ownership,
+}
+
+/// Ownership of `string`/`bytes` data crossing the C ABI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ownership {
+    /// Params: the host passes a temporary copy. Returns: the caller copies the
+    /// result into a host value and frees it with `weaveffi_free_*` (default)
+    #[default]
+    Owned,
+    /// Params: the host passes a zero-copy view valid only for the call.
+    /// Returns: memory stays owned by the callee; the caller must not free it
+    Borrowed,
+    /// Returns only: the host adopts the buffer without copying and frees it
+    /// with `weaveffi_free_*` when the host object is released
+    Transferred,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Ownership {
+    /// Spelling in the IDL.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Ownership::Owned => "owned",
+            Ownership::Borrowed => "borrowed",
+            Ownership::Transferred => "transferred",
+        }
+    }
+}
+
+/// Serialized as a type name string, e.g. `i32`, `string`, `handle<Session>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum TypeRef {
-    #[serde(rename = "i32")] I32,
-    #[serde(rename = "u32")] U32,
-    #[serde(rename = "i64")] I64,
-    #[serde(rename = "f64")] F64,
-    #[serde(rename = "bool")] Bool,
-    #[serde(rename = "string")] StringUtf8,
-    #[serde(rename = "bytes")] Bytes,
-    #[serde(rename = "handle")] Handle,
+    I32,
+    U32,
+    I64,
+    F64,
+    Bool,
+    StringUtf8,
+    Bytes,
+    /// Untyped opaque handle
+    Handle,
+    /// Handle to a resource declared in a module's `handles`
+    TypedHandle(String),
+}
+
+impl fmt::Display for TypeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeRef::I32 => f.write_str("i32"),
+            TypeRef::U32 => f.write_str("u32"),
+            TypeRef::I64 => f.write_str("i64"),
+            TypeRef::F64 => f.write_str("f64"),
+            TypeRef::Bool => f.write_str("bool"),
+            TypeRef::StringUtf8 => f.write_str("string"),
+            TypeRef::Bytes => f.write_str("bytes"),
+            TypeRef::Handle => f.write_str("handle"),
+            TypeRef::TypedHandle(name) => write!(f, "handle<{}>", name),
+        }
+    }
+}
+
+impl FromStr for TypeRef {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "i32" => TypeRef::I32,
+            "u32" => TypeRef::U32,
+            "i64" => TypeRef::I64,
+            "f64" => TypeRef::F64,
+            "bool" => TypeRef::Bool,
+            "string" => TypeRef::StringUtf8,
+            "bytes" => TypeRef::Bytes,
+            "handle" => TypeRef::Handle,
+            other => {
+                let name = other
+                    .strip_prefix("handle<")
+                    .and_then(|rest| rest.strip_suffix('>'))
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+                    .ok_or_else(|| format!("unknown type '{}' (expected i32|u32|i64|f64|bool|string|bytes|handle|handle<Name>)", other))?;
+                TypeRef::TypedHandle(name.to_string())
+            }
+        })
+    }
+}
+
+impl JsonSchema for TypeRef {
+    fn schema_name() -> String { "TypeRef".into() }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, Metadata, SchemaObject, StringValidation};
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some("Type name: i32, u32, i64, f64, bool, string, bytes, handle or handle<Name>".into()),
+                ..Default::default()
+            })),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^\s*(i32|u32|i64|f64|bool|string|bytes|handle|handle<\s*[A-Za-z0-9_]+\s*>)\s*$".into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl TryFrom<String> for TypeRef {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> { s.parse() }
+}
+
+impl From<TypeRef> for String {
+    fn from(t: TypeRef) -> Self { t.to_string() }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ErrorDomain {
     pub name: String,
     pub codes: Vec<ErrorCode>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ErrorCode {
     /// Symbolic name, e.g. "InvalidInput"
     pub name: String,
@@ -62,4 +285,6 @@ pub struct ErrorCode {
     pub code: i32,
     /// Human-readable message
     pub message: String,
+    #[serde(default)]
+    pub doc: Option<String>,
 }
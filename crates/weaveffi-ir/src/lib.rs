@@ -1,4 +1,9 @@
 //! In-memory IR types and (de)serialization for WeaveFFI.
 
+pub mod format;
 pub mod ir;
 pub mod parse;
+pub mod schema;
+pub mod source;
+pub mod weave;
+pub mod wit;
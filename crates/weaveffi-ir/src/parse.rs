@@ -1,4 +1,6 @@
 use crate::ir::Api;
+use crate::weave::{parse_weave, SyntaxError};
+use crate::wit::parse_wit;
 use serde::de::DeserializeOwned;
 
 #[derive(Debug, thiserror::Error)]
@@ -11,6 +13,20 @@ pub enum ParseError {
     Toml { line: usize, column: usize, message: String },
     #[error("JSON parse error at line {line}, column {column}: {message}")]
     Json { line: usize, column: usize, message: String },
+    #[error("{}", weave_errors(.0))]
+    Weave(Vec<SyntaxError>),
+    #[error("{}", wit_errors(.0))]
+    Wit(Vec<String>),
+}
+
+fn weave_errors(errors: &[SyntaxError]) -> String {
+    let lines: Vec<String> = errors.iter().map(|e| format!("  {}", e)).collect();
+    format!("{} syntax error(s) in .weave file:\n{}", errors.len(), lines.join("\n"))
+}
+
+fn wit_errors(errors: &[String]) -> String {
+    let lines: Vec<String> = errors.iter().map(|e| format!("  {}", e)).collect();
+    format!("{} issue(s) importing .wit file:\n{}", errors.len(), lines.join("\n"))
 }
 
 fn from_str_format<T: DeserializeOwned>(s: &str, format: &str) -> Result<T, ParseError> {
@@ -31,5 +47,11 @@ fn from_str_format<T: DeserializeOwned>(s: &str, format: &str) -> Result<T, Pars
 }
 
 pub fn parse_api_str(s: &str, format: &str) -> Result<Api, ParseError> {
+    if format == "weave" {
+        return parse_weave(s).map_err(ParseError::Weave);
+    }
+    if format == "wit" {
+        return parse_wit(s).map_err(ParseError::Wit);
+    }
     from_str_format::<Api>(s, format)
 }
@@ -0,0 +1,16 @@
+//! JSON Schema for the IDL, for editor completion and validation.
+use crate::ir::Api;
+use schemars::schema::RootSchema;
+
+/// Version of the IDL format described by [`idl_schema`]; tracks this crate.
+pub const SCHEMA_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// JSON Schema (draft-07) for an IDL document, derived from [`Api`].
+pub fn idl_schema() -> RootSchema {
+    let mut schema = schemars::schema_for!(Api);
+    let meta = schema.schema.metadata();
+    meta.id = Some(format!("https://weaveffi.dev/schema/idl-{}.json", SCHEMA_VERSION));
+    meta.title = Some(format!("WeaveFFI IDL {}", SCHEMA_VERSION));
+    meta.description = Some("Modules, functions and types exposed over the WeaveFFI C ABI".into());
+    schema
+}
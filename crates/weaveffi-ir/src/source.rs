@@ -0,0 +1,91 @@
+//! Locating IDL items in source text.
+//!
+//! The parsed IR carries no spans, so tools find items by searching for their
+//! names in value position (`name: add`, `"name": "add"`, `name = "add"`,
+//! `handle<Session>`, or after a `.weave` keyword as in `fn add`), never in
+//! keys or prose.
+
+use std::ops::Range;
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte offset where the identifier ending `text` starts.
+fn ident_start(text: &str) -> usize {
+    text.char_indices().rev().find(|&(_, c)| !is_ident(c)).map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// Text before a value, minus its opening quote and whitespace.
+fn value_prefix(text: &str, start: usize) -> &str {
+    text[..start].trim_end_matches(['"', '\'']).trim_end_matches([' ', '\t'])
+}
+
+/// `.weave` keywords followed by a name, and the IDL key that name corresponds to.
+const WEAVE_KEYWORDS: &[(&str, &str)] = &[
+    ("module", "name"),
+    ("fn", "name"),
+    ("handle", "name"),
+    ("error", "name"),
+    ("release", "release"),
+];
+
+/// IDL key for a name following a `.weave` keyword, e.g. "name" for `fn add`.
+fn weave_key(head: &str) -> Option<&'static str> {
+    let word_start = ident_start(head);
+    let (before, word) = head.split_at(word_start);
+    let separated = before.is_empty() || before.ends_with(char::is_whitespace);
+    WEAVE_KEYWORDS.iter().find(|(kw, _)| *kw == word && separated).map(|(_, key)| *key)
+}
+
+/// First whole-word occurrence of `token` at or after `from` in value position.
+pub fn find_value(text: &str, token: &str, from: usize) -> Option<Range<usize>> {
+    if token.is_empty() || from > text.len() {
+        return None;
+    }
+    text[from..].match_indices(token).map(|(i, _)| from + i).find_map(|start| {
+        let end = start + token.len();
+        let whole = !text[..start].chars().next_back().is_some_and(is_ident) && !text[end..].chars().next().is_some_and(is_ident);
+        let value = value_prefix(text, start);
+        let after_key = value.ends_with(':') || value.ends_with('=') || value.ends_with('<');
+        let after_keyword = text[..start].ends_with(char::is_whitespace) && weave_key(value).is_some();
+        (whole && (after_key || after_keyword)).then_some(start..end)
+    })
+}
+
+/// First occurrence of `name` as the value of a `name` key, i.e. where an item is declared.
+pub fn find_declaration(text: &str, name: &str, mut from: usize) -> Option<Range<usize>> {
+    loop {
+        let span = find_value(text, name, from)?;
+        if value_key(text, span.start) == Some("name") {
+            return Some(span);
+        }
+        from = span.end;
+    }
+}
+
+/// Key a value at `start` belongs to, e.g. "release" for `release: free_session`.
+pub fn value_key(text: &str, start: usize) -> Option<&str> {
+    let head = value_prefix(text, start);
+    if let Some(key) = weave_key(head).filter(|_| text[..start].ends_with(char::is_whitespace)) {
+        return Some(key);
+    }
+    let head = head.strip_suffix(':').or_else(|| head.strip_suffix('='))?;
+    let head = head.trim_end().trim_end_matches(['"', '\'']);
+    let key_start = ident_start(head);
+    Some(&head[key_start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_before_a_name() {
+        let text = "modules:\n  - name: calc\n    doc: \"Uses — add\"\n    functions:\n      - name: add\n";
+        let span = find_declaration(text, "add", 0).unwrap();
+        assert_eq!(&text[span.clone()], "add");
+        assert_eq!(value_key(text, span.start), Some("name"));
+        assert_eq!(find_value("doc: \"«add\"", "add", 0), None);
+    }
+}
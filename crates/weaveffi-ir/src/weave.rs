@@ -0,0 +1,625 @@
+//! Parser for `.weave` files, a compact IDL syntax that produces the same `Api`
+//! as the YAML/JSON/TOML forms:
+//!
+//! ```text
+//! version "0.1.0";
+//!
+//! /// Integer arithmetic.
+//! module calculator {
+//!     error CalcError {
+//!         /// Raised by `div`
+//!         DivByZero = 1 "division by zero";
+//!     }
+//!     handle Session release close;
+//!
+//!     /// Integer division.
+//!     @since("0.2.0")
+//!     fn div(/// Dividend
+//!            a: i32, b: i32) -> i32 throws CalcError;
+//!     fn echo(s: borrowed string) -> string;
+//!     fn close(s: handle<Session>);
+//! }
+//! ```
+//!
+//! The parser recovers at the next `;` or `}` after an error, so every syntax
+//! error in a file is reported at once.
+
+use crate::ir::{Api, Deprecation, ErrorCode, ErrorDomain, Function, HandleType, Module, Ownership, Param, TypeRef};
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// A syntax error at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+pub fn parse_weave(src: &str) -> Result<Api, Vec<SyntaxError>> {
+    let (tokens, errors) = lex(src);
+    let mut parser = Parser { tokens, pos: 0, errors };
+    let api = parser.api();
+    if parser.errors.is_empty() {
+        Ok(api)
+    } else {
+        parser.errors.sort_by_key(|e| (e.line, e.column));
+        Err(parser.errors)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    /// One `///` line, without the marker and one leading space
+    Doc(String),
+    Punct(&'static str),
+    Eof,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Ident(s) => write!(f, "`{}`", s),
+            Tok::Int(i) => write!(f, "`{}`", i),
+            Tok::Str(s) => write!(f, "{:?}", s),
+            Tok::Doc(_) => f.write_str("doc comment"),
+            Tok::Punct(p) => write!(f, "`{}`", p),
+            Tok::Eof => f.write_str("end of file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    column: usize,
+}
+
+const PUNCT: &[&str] = &["->", "{", "}", "(", ")", "<", ">", ",", ";", ":", "=", "@"];
+
+/// Position in the source while lexing.
+struct Cursor<'a> {
+    src: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.offset..]
+    }
+
+    /// Advance past `len` bytes, which must end on a char boundary.
+    fn advance(&mut self, len: usize) {
+        for c in self.src[self.offset..self.offset + len].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset += len;
+    }
+}
+
+fn lex(src: &str) -> (Vec<Token>, Vec<SyntaxError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let mut cur = Cursor { src, offset: 0, line: 1, column: 1 };
+    while let Some(c) = cur.rest().chars().next() {
+        let (line, column) = (cur.line, cur.column);
+        let rest = cur.rest();
+        let mut error = |message: String| errors.push(SyntaxError { line, column, message });
+        let tok = if c.is_whitespace() {
+            cur.advance(c.len_utf8());
+            continue;
+        } else if rest.starts_with("//") {
+            let text = rest.split('\n').next().unwrap_or("");
+            cur.advance(text.len());
+            match text.strip_prefix("///") {
+                // `////` is an ordinary comment, like in Rust
+                Some(doc) if !doc.starts_with('/') => Tok::Doc(doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string()),
+                _ => continue,
+            }
+        } else if c == '"' {
+            let mut value = String::new();
+            let mut len = 1;
+            let mut closed = false;
+            let mut escaped = false;
+            for ch in rest[1..].chars() {
+                if ch == '\n' && !escaped {
+                    break;
+                }
+                len += ch.len_utf8();
+                match (escaped, ch) {
+                    (true, 'n') => value.push('\n'),
+                    (true, 't') => value.push('\t'),
+                    (true, other) => value.push(other),
+                    (false, '\\') => {
+                        escaped = true;
+                        continue;
+                    }
+                    (false, '"') => {
+                        closed = true;
+                        break;
+                    }
+                    (false, other) => value.push(other),
+                }
+                escaped = false;
+            }
+            if !closed {
+                error("unterminated string".into());
+            }
+            cur.advance(len);
+            Tok::Str(value)
+        } else if c.is_ascii_digit() || (c == '-' && rest[1..].starts_with(|d: char| d.is_ascii_digit())) {
+            let len = 1 + rest[1..].find(|d: char| !d.is_ascii_digit()).unwrap_or(rest.len() - 1);
+            cur.advance(len);
+            match rest[..len].parse() {
+                Ok(n) => Tok::Int(n),
+                Err(_) => {
+                    error(format!("integer out of range: {}", &rest[..len]));
+                    Tok::Int(0)
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|d: char| !(d.is_alphanumeric() || d == '_')).unwrap_or(rest.len());
+            cur.advance(len);
+            Tok::Ident(rest[..len].to_string())
+        } else if let Some(p) = PUNCT.iter().find(|p| rest.starts_with(**p)) {
+            cur.advance(p.len());
+            Tok::Punct(p)
+        } else {
+            error(format!("unexpected character `{}`", c));
+            cur.advance(c.len_utf8());
+            continue;
+        };
+        tokens.push(Token { tok, line, column });
+    }
+    tokens.push(Token { tok: Tok::Eof, line: cur.line, column: cur.column });
+    (tokens, errors)
+}
+
+const ITEM_KEYWORDS: &[&str] = &["version", "error_convention", "string_return", "module", "fn", "async", "handle", "error", "struct", "enum"];
+
+/// Marker that an error was recorded and the caller should recover.
+struct Recover;
+
+type PResult<T> = Result<T, Recover>;
+
+/// `@since` / `@deprecated` attributes preceding an item.
+#[derive(Default)]
+struct Attributes {
+    since: Option<String>,
+    deprecated: Option<Deprecation>,
+    first: Option<Token>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    errors: Vec<SyntaxError>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn bump(&mut self) -> Token {
+        let t = self.tokens[self.pos].clone();
+        if t.tok != Tok::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn at_punct(&self, p: &str) -> bool {
+        matches!(self.peek().tok, Tok::Punct(q) if q == p)
+    }
+
+    fn at_keyword(&self, kw: &str) -> bool {
+        matches!(&self.peek().tok, Tok::Ident(s) if s == kw)
+    }
+
+    fn eat_punct(&mut self, p: &str) -> bool {
+        let at = self.at_punct(p);
+        if at {
+            self.bump();
+        }
+        at
+    }
+
+    fn error_at(&mut self, at: &Token, message: impl Into<String>) -> Recover {
+        self.errors.push(SyntaxError { line: at.line, column: at.column, message: message.into() });
+        Recover
+    }
+
+    fn unexpected(&mut self, expected: &str) -> Recover {
+        let at = self.peek().clone();
+        self.error_at(&at, format!("expected {}, found {}", expected, at.tok))
+    }
+
+    fn expect_punct(&mut self, p: &str) -> PResult<()> {
+        if self.eat_punct(p) { Ok(()) } else { Err(self.unexpected(&format!("`{}`", p))) }
+    }
+
+    fn expect_ident(&mut self, what: &str) -> PResult<String> {
+        match &self.peek().tok {
+            Tok::Ident(s) => {
+                let s = s.clone();
+                self.bump();
+                Ok(s)
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    fn expect_str(&mut self, what: &str) -> PResult<String> {
+        match &self.peek().tok {
+            Tok::Str(s) => {
+                let s = s.clone();
+                self.bump();
+                Ok(s)
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    /// Whether the next token begins a line with something that starts an item,
+    /// which is a safe place to resume after a missing `;`.
+    fn at_item_start(&self) -> bool {
+        let starts_line = self.pos == 0 || self.tokens[self.pos - 1].line < self.peek().line;
+        let item = match &self.peek().tok {
+            Tok::Ident(s) => ITEM_KEYWORDS.contains(&s.as_str()),
+            Tok::Doc(_) | Tok::Punct("@") => true,
+            _ => false,
+        };
+        starts_line && item
+    }
+
+    /// Skip past the next `;`, up to the `}` closing the current block, or up to
+    /// the next item on a new line.
+    fn recover(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            if depth == 0 && self.at_item_start() {
+                return;
+            }
+            match &self.peek().tok {
+                Tok::Eof => return,
+                Tok::Punct(";") if depth == 0 => {
+                    self.bump();
+                    return;
+                }
+                Tok::Punct("{") => depth += 1,
+                Tok::Punct("}") if depth == 0 => return,
+                Tok::Punct("}") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.bump();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    fn docs(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while let Tok::Doc(line) = &self.peek().tok {
+            lines.push(line.clone());
+            self.bump();
+        }
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    fn attributes(&mut self) -> PResult<Attributes> {
+        let mut attrs = Attributes::default();
+        while self.at_punct("@") {
+            let at = self.bump();
+            attrs.first.get_or_insert(at);
+            let name_tok = self.peek().clone();
+            match self.expect_ident("attribute name")?.as_str() {
+                "since" => {
+                    self.expect_punct("(")?;
+                    attrs.since = Some(self.expect_str("version string")?);
+                    self.expect_punct(")")?;
+                }
+                "deprecated" => {
+                    let mut d = Deprecation::default();
+                    if self.eat_punct("(") {
+                        while !self.eat_punct(")") {
+                            let key_tok = self.peek().clone();
+                            let key = self.expect_ident("`since`, `message` or `replacement`")?;
+                            self.expect_punct("=")?;
+                            match key.as_str() {
+                                "since" => d.since = Some(self.expect_str("version string")?),
+                                "message" => d.message = Some(self.expect_str("message string")?),
+                                "replacement" => d.replacement = Some(self.expect_ident("function name")?),
+                                other => return Err(self.error_at(&key_tok, format!("unknown deprecation argument `{}`", other))),
+                            }
+                            if !self.at_punct(")") {
+                                self.expect_punct(",")?;
+                            }
+                        }
+                    }
+                    attrs.deprecated = Some(d);
+                }
+                other => return Err(self.error_at(&name_tok, format!("unknown attribute `@{}` (expected @since or @deprecated)", other))),
+            }
+        }
+        Ok(attrs)
+    }
+
+    fn api(&mut self) -> Api {
+        let mut api = Api {
+            version: String::new(),
+            modules: Vec::new(),
+            error_convention: Default::default(),
+            string_return: Default::default(),
+        };
+        let mut has_version = false;
+        while self.peek().tok != Tok::Eof {
+            let start = self.pos;
+            if let Err(Recover) = self.top_level_item(&mut api, &mut has_version) {
+                self.recover();
+                if self.pos == start || self.at_punct("}") {
+                    // Always make progress, including past a stray `}`
+                    self.bump();
+                }
+            }
+        }
+        if !has_version {
+            self.errors.push(SyntaxError { line: 1, column: 1, message: "missing `version \"x.y.z\";` declaration".into() });
+        }
+        api
+    }
+
+    fn top_level_item(&mut self, api: &mut Api, has_version: &mut bool) -> PResult<()> {
+        let doc = self.docs();
+        let attrs = self.attributes()?;
+        let start = self.peek().clone();
+        let keyword = self.expect_ident("`version`, `error_convention`, `string_return` or `module`")?;
+        if keyword != "module" {
+            self.reject_annotations(&start, doc.as_deref(), &attrs)?;
+        }
+        match keyword.as_str() {
+            "version" => {
+                api.version = self.expect_str("version string")?;
+                *has_version = true;
+            }
+            "error_convention" => api.error_convention = self.setting("error convention")?,
+            "string_return" => api.string_return = self.setting("string return mode")?,
+            "module" => {
+                let module = self.module(doc, attrs)?;
+                api.modules.push(module);
+                return Ok(());
+            }
+            other => return Err(self.error_at(&start, format!("expected `version`, `error_convention`, `string_return` or `module`, found `{}`", other))),
+        }
+        self.expect_punct(";")
+    }
+
+    fn reject_annotations(&mut self, item: &Token, doc: Option<&str>, attrs: &Attributes) -> PResult<()> {
+        if let Some(at) = &attrs.first {
+            return Err(self.error_at(at, "attributes are only allowed on modules and functions"));
+        }
+        if doc.is_some() {
+            return Err(self.error_at(item, "doc comments are only allowed on modules, functions, params and error codes"));
+        }
+        Ok(())
+    }
+
+    /// Snake-case setting value, e.g. `last_error`.
+    fn setting<T: DeserializeOwned>(&mut self, what: &str) -> PResult<T> {
+        let at = self.peek().clone();
+        let value = self.expect_ident(what)?;
+        serde_json::from_value(serde_json::Value::String(value.clone()))
+            .map_err(|_| self.error_at(&at, format!("unknown {} `{}`", what, value)))
+    }
+
+    fn module(&mut self, doc: Option<String>, attrs: Attributes) -> PResult<Module> {
+        let name = self.expect_ident("module name")?;
+        self.expect_punct("{")?;
+        let mut module = Module {
+            name,
+            doc,
+            functions: Vec::new(),
+            errors: None,
+            handles: Vec::new(),
+            since: attrs.since,
+            deprecated: attrs.deprecated,
+        };
+        let mut throws = Vec::new();
+        while !self.eat_punct("}") {
+            if self.peek().tok == Tok::Eof {
+                return Err(self.unexpected("`}` closing the module"));
+            }
+            let start = self.pos;
+            if let Err(Recover) = self.module_item(&mut module, &mut throws) {
+                self.recover();
+                if self.pos == start {
+                    self.bump();
+                }
+            }
+        }
+        for at in throws {
+            let Tok::Ident(domain) = &at.tok else { continue };
+            if module.errors.as_ref().map(|e| &e.name) != Some(domain) {
+                self.error_at(&at, format!("unknown error domain `{}` in module `{}`", domain, module.name));
+            }
+        }
+        Ok(module)
+    }
+
+    /// One item inside `module { ... }`; `throws` collects `throws` clauses to check
+    /// once the module's error domain is known.
+    fn module_item(&mut self, module: &mut Module, throws: &mut Vec<Token>) -> PResult<()> {
+        let doc = self.docs();
+        let attrs = self.attributes()?;
+        let start = self.peek().clone();
+        match self.expect_ident("`fn`, `async fn`, `handle`, `error`, `struct` or `enum`")?.as_str() {
+            "fn" => module.functions.push(self.function(doc, attrs, false, throws)?),
+            "async" => {
+                if !self.at_keyword("fn") {
+                    return Err(self.unexpected("`fn`"));
+                }
+                self.bump();
+                module.functions.push(self.function(doc, attrs, true, throws)?);
+            }
+            "handle" => {
+                self.reject_annotations(&start, doc.as_deref(), &attrs)?;
+                let name = self.expect_ident("handle type name")?;
+                let release = if self.at_keyword("release") {
+                    self.bump();
+                    Some(self.expect_ident("release function name")?)
+                } else {
+                    None
+                };
+                self.expect_punct(";")?;
+                module.handles.push(HandleType { name, release });
+            }
+            "error" => {
+                self.reject_annotations(&start, doc.as_deref(), &attrs)?;
+                if module.errors.is_some() {
+                    return Err(self.error_at(&start, format!("module `{}` already has an error domain", module.name)));
+                }
+                module.errors = Some(self.error_domain()?);
+            }
+            kind @ ("struct" | "enum") => {
+                let kind = kind.to_string();
+                self.expect_ident(&format!("{} name", kind))?;
+                self.expect_punct("{")?;
+                while !self.eat_punct("}") {
+                    if self.bump().tok == Tok::Eof {
+                        return Err(self.unexpected("`}`"));
+                    }
+                }
+                return Err(self.error_at(&start, format!("`{}` declarations are not supported by the IR yet", kind)));
+            }
+            other => return Err(self.error_at(&start, format!("expected `fn`, `handle`, `error`, `struct` or `enum`, found `{}`", other))),
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, doc: Option<String>, attrs: Attributes, is_async: bool, throws: &mut Vec<Token>) -> PResult<Function> {
+        let name = self.expect_ident("function name")?;
+        self.expect_punct("(")?;
+        let mut params = Vec::new();
+        while !self.eat_punct(")") {
+            let doc = self.docs();
+            let name = self.expect_ident("parameter name or `)`")?;
+            self.expect_punct(":")?;
+            let ownership = self.ownership();
+            let ty = self.type_ref()?;
+            params.push(Param { name, ty, doc, ownership: ownership.unwrap_or_default() });
+            if !self.at_punct(")") {
+                self.expect_punct(",")?;
+            }
+        }
+        let (mut returns, mut return_ownership) = (None, Ownership::default());
+        if self.eat_punct("->") {
+            return_ownership = self.ownership().unwrap_or_default();
+            returns = Some(self.type_ref()?);
+        }
+        if self.at_keyword("throws") {
+            self.bump();
+            throws.push(self.peek().clone());
+            self.expect_ident("error domain name")?;
+        }
+        self.expect_punct(";")?;
+        Ok(Function {
+            name,
+            params,
+            returns,
+            return_ownership,
+            doc,
+            since: attrs.since,
+            deprecated: attrs.deprecated,
+            r#async: is_async,
+        })
+    }
+
+    fn ownership(&mut self) -> Option<Ownership> {
+        let ownership = match &self.peek().tok {
+            Tok::Ident(s) if s == "owned" => Ownership::Owned,
+            Tok::Ident(s) if s == "borrowed" => Ownership::Borrowed,
+            Tok::Ident(s) if s == "transferred" => Ownership::Transferred,
+            _ => return None,
+        };
+        self.bump();
+        Some(ownership)
+    }
+
+    fn type_ref(&mut self) -> PResult<TypeRef> {
+        let at = self.peek().clone();
+        let mut name = self.expect_ident("type")?;
+        if name == "handle" && self.eat_punct("<") {
+            name = format!("handle<{}>", self.expect_ident("handle type name")?);
+            self.expect_punct(">")?;
+        }
+        name.parse().map_err(|e: String| self.error_at(&at, e))
+    }
+
+    fn error_domain(&mut self) -> PResult<ErrorDomain> {
+        let name = self.expect_ident("error domain name")?;
+        self.expect_punct("{")?;
+        let mut codes = Vec::new();
+        while !self.eat_punct("}") {
+            if self.peek().tok == Tok::Eof {
+                return Err(self.unexpected("`}` closing the error domain"));
+            }
+            let start = self.pos;
+            let mut code = || -> PResult<ErrorCode> {
+                let doc = self.docs();
+                let name = self.expect_ident("error code name")?;
+                self.expect_punct("=")?;
+                let code = match self.peek().tok {
+                    Tok::Int(n) => n,
+                    _ => return Err(self.unexpected("error code number")),
+                };
+                let at = self.bump();
+                let code = i32::try_from(code).map_err(|_| self.error_at(&at, "error code must fit in 32 bits"))?;
+                let message = self.expect_str("error message string")?;
+                self.expect_punct(";")?;
+                Ok(ErrorCode { name, code, message, doc })
+            };
+            match code() {
+                Ok(c) => codes.push(c),
+                Err(Recover) => {
+                    self.recover();
+                    if self.pos == start {
+                        self.bump();
+                    }
+                }
+            }
+        }
+        Ok(ErrorDomain { name, codes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_domain_recovers_from_an_item_start() {
+        let src = "version \"0.1.0\";\nmodule m {\n  error E {\n    @since(\"0.1.0\")\n    A = 1 \"a\";\n  }\n}\n";
+        assert!(parse_weave(src).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! WIT frontend: translates a `.wit` package into an `Api`.
+//!
+//! Each interface in the package becomes a module, as do a world's inline
+//! interfaces and its freestanding functions (in a module named after the
+//! world). Kebab-case names become snake_case. Resources become typed handles
+//! with `<resource>_new` and `<resource>_drop` functions, methods take the
+//! handle as their first param, and `result<T, E>` with an enum `E` becomes the
+//! module's error domain. The IR has no aggregate types, so records, variants,
+//! options and lists other than `list<u8>` are rejected rather than mapped; every
+//! such use is reported together instead of being approximated.
+
+use crate::ir::{Api, ErrorCode, ErrorDomain, Function, HandleType, Module, Ownership, Param, TypeRef};
+use heck::{ToSnakeCase, ToUpperCamelCase};
+use wit_parser::{
+    Docs, Function as WitFunction, FunctionKind, Handle, Resolve, Type, TypeDefKind, TypeId, TypeOwner, WorldItem, WorldKey,
+};
+
+/// Translate the WIT package in `src`. Errors are either the WIT parse error
+/// or one message per construct that has no IR equivalent.
+pub fn parse_wit(src: &str) -> Result<Api, Vec<String>> {
+    let mut resolve = Resolve::new();
+    let pkg = resolve.push_str("input.wit", src).map_err(|e| vec![format!("{:#}", e)])?;
+    let mut importer = Importer { resolve: &resolve, issues: Vec::new() };
+    let package = &resolve.packages[pkg];
+    let mut modules = Vec::new();
+    for (name, &id) in &package.interfaces {
+        let iface = &resolve.interfaces[id];
+        modules.push(importer.module(name, &iface.docs, iface.types.values().copied(), iface.functions.values()));
+    }
+    for &world_id in package.worlds.values() {
+        let world = &resolve.worlds[world_id];
+        let mut functions = Vec::new();
+        for (key, item) in world.imports.iter().chain(world.exports.iter()) {
+            match (key, item) {
+                (WorldKey::Name(name), WorldItem::Interface { id, .. }) => {
+                    let iface = &resolve.interfaces[*id];
+                    modules.push(importer.module(name, &iface.docs, iface.types.values().copied(), iface.functions.values()));
+                }
+                (WorldKey::Interface(id), WorldItem::Interface { .. }) if resolve.interfaces[*id].package != Some(pkg) => {
+                    let name = resolve.id_of(*id).unwrap_or_else(|| "<anonymous>".into());
+                    importer.issues.push(format!(
+                        "world `{}`: interface `{}` belongs to another package; import that package separately",
+                        world.name, name
+                    ));
+                }
+                (_, WorldItem::Function(f)) => functions.push(f),
+                _ => {}
+            }
+        }
+        if !functions.is_empty() {
+            let types: Vec<TypeId> = resolve
+                .types
+                .iter()
+                .filter(|(_, t)| t.owner == TypeOwner::World(world_id))
+                .map(|(id, _)| id)
+                .collect();
+            modules.push(importer.module(&world.name, &world.docs, types.into_iter(), functions.into_iter()));
+        }
+    }
+    if !importer.issues.is_empty() {
+        return Err(importer.issues);
+    }
+    Ok(Api {
+        version: package.name.version.as_ref().map_or_else(|| "0.1.0".into(), |v| v.to_string()),
+        modules,
+        error_convention: Default::default(),
+        string_return: Default::default(),
+    })
+}
+
+fn doc(docs: &Docs) -> Option<String> {
+    docs.contents.as_ref().map(|d| d.trim().to_string()).filter(|d| !d.is_empty())
+}
+
+struct Importer<'a> {
+    resolve: &'a Resolve,
+    issues: Vec<String>,
+}
+
+impl Importer<'_> {
+    fn type_name(&self, id: TypeId) -> &str {
+        self.resolve.types[id].name.as_deref().unwrap_or("<anonymous>")
+    }
+
+    fn handle(&self, resource: TypeId) -> TypeRef {
+        TypeRef::TypedHandle(self.type_name(resource).to_upper_camel_case())
+    }
+
+    fn module<'f>(
+        &mut self,
+        name: &str,
+        docs: &Docs,
+        types: impl Iterator<Item = TypeId>,
+        functions: impl Iterator<Item = &'f WitFunction>,
+    ) -> Module {
+        let mut module = Module {
+            name: name.to_snake_case(),
+            doc: doc(docs),
+            functions: Vec::new(),
+            errors: None,
+            handles: Vec::new(),
+            since: None,
+            deprecated: None,
+        };
+        for id in types {
+            if let TypeDefKind::Resource = self.resolve.types[id].kind {
+                let resource = self.type_name(id).to_snake_case();
+                let release = format!("{}_drop", resource);
+                module.handles.push(HandleType { name: resource.to_upper_camel_case(), release: Some(release.clone()) });
+                module.functions.push(Function {
+                    name: release,
+                    params: vec![Param { name: resource, ty: self.handle(id), doc: None, ownership: Ownership::Owned }],
+                    returns: None,
+                    return_ownership: Ownership::Owned,
+                    doc: Some(format!("Releases a `{}`.", self.type_name(id))),
+                    since: None,
+                    deprecated: None,
+                    r#async: false,
+                });
+            }
+        }
+        for f in functions {
+            if let Some(function) = self.function(&mut module, f) {
+                module.functions.push(function);
+            }
+        }
+        module
+    }
+
+    fn function(&mut self, module: &mut Module, f: &WitFunction) -> Option<Function> {
+        let what = match f.kind.resource() {
+            Some(r) => format!("{}.{}.{}", module.name, self.type_name(r), f.item_name()),
+            None => format!("{}.{}", module.name, f.name),
+        };
+        let mut params = Vec::new();
+        let mut wit_params = f.params.iter();
+        let name = match f.kind {
+            FunctionKind::Freestanding | FunctionKind::AsyncFreestanding => f.name.to_snake_case(),
+            FunctionKind::Method(r) | FunctionKind::AsyncMethod(r) => {
+                // The implicit `self: borrow<r>` is passed as the resource's handle,
+                // named after the resource unless a declared param already is
+                wit_params.next();
+                let resource = self.type_name(r).to_snake_case();
+                let taken = f.params.iter().skip(1).any(|(param, _)| param.to_snake_case() == resource);
+                let self_name = if taken { "self_".to_string() } else { resource.clone() };
+                params.push(Param { name: self_name, ty: self.handle(r), doc: None, ownership: Ownership::Owned });
+                format!("{}_{}", resource, f.item_name().to_snake_case())
+            }
+            FunctionKind::Static(r) | FunctionKind::AsyncStatic(r) => {
+                format!("{}_{}", self.type_name(r).to_snake_case(), f.item_name().to_snake_case())
+            }
+            FunctionKind::Constructor(r) => format!("{}_new", self.type_name(r).to_snake_case()),
+        };
+        let mut ok = true;
+        for (param, ty) in wit_params {
+            match self.type_ref(ty, &format!("{}: param `{}`", what, param)) {
+                Some(ty) => params.push(Param { name: param.to_snake_case(), ty, doc: None, ownership: Ownership::Owned }),
+                None => ok = false,
+            }
+        }
+        let returns = match f.result {
+            None => None,
+            Some(ty) => match self.result_parts(ty) {
+                Some((value, err)) => {
+                    self.error_domain(module, err, &what);
+                    match value {
+                        Some(v) => Some(self.type_ref(&v, &format!("{}: result", what))?),
+                        None => None,
+                    }
+                }
+                None => Some(self.type_ref(&ty, &format!("{}: result", what))?),
+            },
+        };
+        ok.then(|| Function {
+            name,
+            params,
+            returns,
+            return_ownership: Ownership::Owned,
+            doc: doc(&f.docs),
+            since: None,
+            deprecated: None,
+            r#async: f.kind.is_async(),
+        })
+    }
+
+    /// `result<T, E>` split into its ok and error types, following aliases.
+    fn result_parts(&self, ty: Type) -> Option<(Option<Type>, Option<Type>)> {
+        match &self.resolve.types[self.alias_target(ty)?].kind {
+            TypeDefKind::Result(r) => Some((r.ok, r.err)),
+            _ => None,
+        }
+    }
+
+    /// The type definition behind `ty`, looking through `type x = y` aliases.
+    fn alias_target(&self, mut ty: Type) -> Option<TypeId> {
+        loop {
+            let Type::Id(id) = ty else { return None };
+            match &self.resolve.types[id].kind {
+                TypeDefKind::Type(inner) => ty = *inner,
+                _ => return Some(id),
+            }
+        }
+    }
+
+    /// Make the enum `err` the module's error domain; codes number its cases from 1.
+    fn error_domain(&mut self, module: &mut Module, err: Option<Type>, what: &str) {
+        let Some(err) = err else { return };
+        let cases = self.alias_target(err).and_then(|id| match &self.resolve.types[id].kind {
+            TypeDefKind::Enum(e) => Some((id, &e.cases)),
+            _ => None,
+        });
+        let Some((id, cases)) = cases else {
+            self.issues.push(format!("{}: the error type of a `result` must be an enum", what));
+            return;
+        };
+        let name = self.type_name(id).to_upper_camel_case();
+        match &module.errors {
+            Some(existing) if existing.name == name => {}
+            Some(existing) => self.issues.push(format!(
+                "{}: module `{}` already reports errors as `{}`; use one error enum per interface",
+                what, module.name, existing.name
+            )),
+            None => {
+                let codes = cases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, case)| {
+                        let doc = doc(&case.docs);
+                        let message = doc
+                            .as_deref()
+                            .and_then(|d| d.lines().next())
+                            .map_or_else(|| case.name.replace('-', " "), str::to_string);
+                        ErrorCode {
+                            name: case.name.to_upper_camel_case(),
+                            code: i as i32 + 1,
+                            doc: doc.filter(|d| *d != message),
+                            message,
+                        }
+                    })
+                    .collect();
+                module.errors = Some(ErrorDomain { name, codes });
+            }
+        }
+    }
+
+    fn type_ref(&mut self, ty: &Type, what: &str) -> Option<TypeRef> {
+        let unsupported = match ty {
+            Type::Bool => return Some(TypeRef::Bool),
+            Type::S32 => return Some(TypeRef::I32),
+            Type::U32 => return Some(TypeRef::U32),
+            Type::S64 => return Some(TypeRef::I64),
+            Type::F64 => return Some(TypeRef::F64),
+            Type::String => return Some(TypeRef::StringUtf8),
+            Type::U8 | Type::U16 | Type::S8 | Type::S16 => "8- and 16-bit integers".to_string(),
+            Type::U64 => "`u64`".to_string(),
+            Type::F32 => "`f32`".to_string(),
+            Type::Char => "`char`".to_string(),
+            Type::ErrorContext => "`error-context`".to_string(),
+            Type::Id(id) => {
+                let name = self.type_name(*id).to_string();
+                match &self.resolve.types[*id].kind {
+                    TypeDefKind::Type(inner) => return self.type_ref(inner, what),
+                    TypeDefKind::List(Type::U8) => return Some(TypeRef::Bytes),
+                    TypeDefKind::Handle(Handle::Own(r)) => return Some(self.handle(*r)),
+                    TypeDefKind::Handle(Handle::Borrow(r)) => {
+                        self.issues.push(format!(
+                            "{}: `borrow<{}>` is not supported; IR handles have no borrow semantics, so pass an owned handle",
+                            what,
+                            self.type_name(*r)
+                        ));
+                        return None;
+                    }
+                    TypeDefKind::Record(_) => format!("record `{}`", name),
+                    TypeDefKind::Variant(_) => format!("variant `{}`", name),
+                    TypeDefKind::Enum(_) => format!("enum `{}` outside a `result` error", name),
+                    TypeDefKind::Flags(_) => format!("flags `{}`", name),
+                    TypeDefKind::Option(_) => "`option`".to_string(),
+                    TypeDefKind::Result(_) => "`result` outside a function result".to_string(),
+                    TypeDefKind::List(_) | TypeDefKind::FixedSizeList(..) => "lists other than `list<u8>`".to_string(),
+                    TypeDefKind::Tuple(_) => "tuples".to_string(),
+                    TypeDefKind::Map(..) => "maps".to_string(),
+                    TypeDefKind::Future(_) | TypeDefKind::Stream(_) => "futures and streams".to_string(),
+                    TypeDefKind::Resource | TypeDefKind::Unknown => format!("type `{}`", name),
+                }
+            }
+        };
+        self.issues.push(format!("{}: {} cannot be represented in the WeaveFFI IR yet", what, unsupported));
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_self_param_does_not_collide() {
+        let api = parse_wit(
+            "package example:files;\ninterface files {\n  resource doc {\n    link: func(doc: own<doc>);\n    title: func() -> string;\n  }\n}\n",
+        )
+        .unwrap();
+        let names = |f: &str| -> Vec<String> {
+            let f = api.modules[0].functions.iter().find(|g| g.name == f).unwrap();
+            f.params.iter().map(|p| p.name.clone()).collect()
+        };
+        assert_eq!(names("doc_link"), ["self_", "doc"]);
+        assert_eq!(names("doc_title"), ["doc"]);
+    }
+}
@@ -0,0 +1,311 @@
+//! Text-level queries over an IDL document: diagnostics, definitions, hover and rename.
+//!
+//! Items are located by searching for their names in nesting order (module,
+//! then function, then param) with `weaveffi_ir::source`.
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+use std::fmt::Write as _;
+use std::ops::Range as Span;
+use weaveffi_core::templates::{c_prototype, doc_lines, effective_deprecation, swift_signature, ts_signature};
+use weaveffi_core::validate::{validate_api, ValidationError};
+use weaveffi_gen_android::kotlin_signature;
+use weaveffi_ir::ir::{Api, Module};
+use weaveffi_ir::parse::{parse_api_str, ParseError};
+use weaveffi_ir::source::{find_value, value_key};
+
+/// An open IDL file and the result of parsing it.
+pub struct Document {
+    pub text: String,
+    parsed: Result<Api, ParseError>,
+}
+
+impl Document {
+    pub fn new(text: String, format: &str) -> Self {
+        let parsed = parse_api_str(&text, format);
+        Self { text, parsed }
+    }
+
+    /// The parsed API, even if it fails validation.
+    pub fn api(&self) -> Option<&Api> {
+        self.parsed.as_ref().ok()
+    }
+
+    /// Parse errors (every one, for `.weave`), or the first validation error
+    /// anchored to the offending item.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let found = match &self.parsed {
+            Err(ParseError::Weave(errors)) => {
+                errors.iter().map(|e| (self.line_range(e.line, e.column), e.message.clone())).collect()
+            }
+            Err(e) => {
+                let (line, column) = match e {
+                    ParseError::Yaml { line, column, .. }
+                    | ParseError::Json { line, column, .. }
+                    | ParseError::Toml { line, column, .. } => (*line, *column),
+                    ParseError::UnsupportedFormat(_) | ParseError::Weave(_) | ParseError::Wit(_) => (0, 0),
+                };
+                vec![(self.line_range(line, column), e.to_string())]
+            }
+            Ok(api) => match validate_api(api) {
+                Ok(()) => Vec::new(),
+                Err(e) => {
+                    let path = error_path(&e);
+                    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+                    let span = locate(&self.text, &path).unwrap_or(0..0);
+                    vec![(self.range(span), e.to_string())]
+                }
+            },
+        };
+        found
+            .into_iter()
+            .map(|(range, message)| Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("weaveffi".into()),
+                message,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// From a parser's 1-based position (0 when unknown) to the end of that line.
+    fn line_range(&self, line: usize, column: usize) -> Range {
+        // Weave columns count chars, which match UTF-16 units outside the astral planes
+        let start = self.offset(Position::new(line.saturating_sub(1) as u32, column.saturating_sub(1) as u32));
+        let end = self.text[start..].find('\n').map_or(self.text.len(), |i| start + i);
+        self.range(start..end)
+    }
+
+    /// Byte offset of an LSP position (UTF-16 columns), clamped to the line end.
+    pub fn offset(&self, pos: Position) -> usize {
+        let line_start: usize = self.text.split_inclusive('\n').take(pos.line as usize).map(str::len).sum();
+        let line = self.text[line_start..].split('\n').next().unwrap_or("");
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            if units >= pos.character {
+                return line_start + i;
+            }
+            units += c.len_utf16() as u32;
+        }
+        line_start + line.len()
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Position::new(before.matches('\n').count() as u32, before[line_start..].encode_utf16().count() as u32)
+    }
+
+    pub fn range(&self, span: Span<usize>) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+
+    /// Identifier under the cursor and its span.
+    pub fn word_at(&self, offset: usize) -> Option<(&str, Span<usize>)> {
+        let start = self.text[..offset].char_indices().rev().find(|&(_, c)| !is_ident(c)).map_or(0, |(i, c)| i + c.len_utf8());
+        let end = self.text[offset..].find(|c| !is_ident(c)).map_or(self.text.len(), |i| offset + i);
+        (start < end).then(|| (&self.text[start..end], start..end))
+    }
+
+    /// Declarations named `word` in this document.
+    pub fn definitions(&self, word: &str) -> Vec<Span<usize>> {
+        let Some(api) = self.api() else { return Vec::new() };
+        symbols(api).iter().filter(|s| s.name() == word).filter_map(|s| locate(&self.text, &s.path)).collect()
+    }
+
+    /// Every reference to function `module::function` that a rename must rewrite:
+    /// its declaration plus `release:` and `replacement:` values naming it.
+    pub fn function_references(&self, module: &str, function: &str) -> Vec<Span<usize>> {
+        let Some(api) = self.api() else { return Vec::new() };
+        let Some((_, region)) = module_regions(&self.text, api).into_iter().find(|(m, _)| m.name == module) else {
+            return Vec::new();
+        };
+        let mut spans = Vec::new();
+        let mut from = region.start;
+        while let Some(span) = find_value(&self.text, function, from).filter(|s| s.end <= region.end) {
+            from = span.end;
+            if matches!(value_key(&self.text, span.start), Some("name" | "release" | "replacement")) {
+                spans.push(span);
+            }
+        }
+        spans
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Module,
+    Function,
+    Handle,
+    ErrorDomain,
+    ErrorCode,
+}
+
+/// A named IDL item and the chain of names leading to it in the text.
+pub struct Symbol<'a> {
+    pub kind: SymbolKind,
+    pub module: &'a Module,
+    pub path: Vec<&'a str>,
+}
+
+impl Symbol<'_> {
+    pub fn name(&self) -> &str {
+        self.path.last().copied().unwrap_or_default()
+    }
+}
+
+pub fn symbols<'a>(api: &'a Api) -> Vec<Symbol<'a>> {
+    fn symbol<'a>(kind: SymbolKind, module: &'a Module, rest: &[&'a str]) -> Symbol<'a> {
+        let mut path = vec![module.name.as_str()];
+        path.extend_from_slice(rest);
+        Symbol { kind, module, path }
+    }
+    let mut out = Vec::new();
+    for m in &api.modules {
+        let at = |kind, rest: &[&'a str]| symbol(kind, m, rest);
+        out.push(at(SymbolKind::Module, &[]));
+        out.extend(m.functions.iter().map(|f| at(SymbolKind::Function, &[&f.name])));
+        out.extend(m.handles.iter().map(|h| at(SymbolKind::Handle, &[&h.name])));
+        if let Some(errors) = &m.errors {
+            out.push(at(SymbolKind::ErrorDomain, &[&errors.name]));
+            out.extend(errors.codes.iter().map(|c| at(SymbolKind::ErrorCode, &[&errors.name, &c.name])));
+        }
+    }
+    out
+}
+
+/// Markdown hover for the item named `word`: signatures in every target for
+/// functions, summaries for handles, error domains and modules.
+pub fn hover(api: &Api, word: &str) -> Option<String> {
+    let symbol = symbols(api).into_iter().find(|s| s.name() == word)?;
+    let m = symbol.module;
+    let mut out = String::new();
+    match symbol.kind {
+        SymbolKind::Function => {
+            let f = m.functions.iter().find(|f| f.name == word)?;
+            writeln!(out, "**{}::{}**", m.name, f.name).ok();
+            push_doc(&mut out, f.doc.as_deref());
+            if let Some(d) = effective_deprecation(m, f) {
+                writeln!(out, "\n*Deprecated:* {}", d.note()).ok();
+            }
+            for (lang, sig) in [
+                ("c", c_prototype(m, f, api)),
+                ("swift", swift_signature(f)),
+                ("kotlin", kotlin_signature(f)),
+                ("typescript", ts_signature(f)),
+            ] {
+                writeln!(out, "\n```{}\n{}\n```", lang, sig).ok();
+            }
+        }
+        SymbolKind::Handle => {
+            let h = m.handles.iter().find(|h| h.name == word)?;
+            writeln!(out, "**handle<{}>** (module `{}`)", h.name, m.name).ok();
+            if let Some(release) = &h.release {
+                writeln!(out, "\nReleased by `{}`", release).ok();
+            }
+        }
+        SymbolKind::ErrorDomain | SymbolKind::ErrorCode => {
+            let errors = m.errors.as_ref()?;
+            writeln!(out, "**{}** (error domain of `{}`)\n", errors.name, m.name).ok();
+            out.push_str("| Code | Name | Message |\n|---|---|---|\n");
+            for c in errors.codes.iter().filter(|c| symbol.kind == SymbolKind::ErrorDomain || c.name == word) {
+                writeln!(out, "| {} | `{}` | {} |", c.code, c.name, c.message).ok();
+            }
+        }
+        SymbolKind::Module => {
+            writeln!(out, "**module {}** ({} functions)", m.name, m.functions.len()).ok();
+            push_doc(&mut out, m.doc.as_deref());
+        }
+    }
+    Some(out)
+}
+
+fn push_doc(out: &mut String, doc: Option<&str>) {
+    let lines = doc_lines(doc);
+    if !lines.is_empty() {
+        writeln!(out, "\n{}", lines.join("\n")).ok();
+    }
+}
+
+pub fn is_identifier(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Names leading to the item an error is about, outermost first.
+fn error_path(e: &ValidationError) -> Vec<String> {
+    use ValidationError::*;
+    let owned = |parts: &[&String]| parts.iter().map(|s| s.to_string()).collect();
+    match e {
+        NoModuleName => Vec::new(),
+        // Duplicates point at the second occurrence
+        DuplicateModuleName(m) => owned(&[m, m]),
+        DuplicateHandleName(h) => owned(&[h, h]),
+        DuplicateFunctionName { module, function } => owned(&[module, function, function]),
+        DuplicateParamName { module, function, param } => owned(&[module, function, param, param]),
+        DuplicateErrorName { module, name } => owned(&[module, name, name]),
+        DuplicateErrorCode { module, code } => vec![module.clone(), code.to_string(), code.to_string()],
+        InvalidModuleName(m, _) | ErrorDomainMissingName(m) => owned(&[m]),
+        ReservedKeyword(name) | ReservedHandleName(name) | HandleNameCollidesWithModule { handle: name, .. } => owned(&[name]),
+        AsyncNotSupported { module, function } | ReservedFunctionName { module, function, .. } => owned(&[module, function]),
+        InvalidErrorCode { module, name }
+        | NameCollisionWithErrorDomain { module, name }
+        | HandleMissingRelease { module, name } => owned(&[module, name]),
+        UnknownReleaseFunction { module, handle, function } | InvalidReleaseFunction { module, handle, function } => {
+            owned(&[module, handle, function])
+        }
+        UnknownHandleType { module, function, name } => owned(&[module, function, name]),
+        OwnershipOnNonBuffer { module, function, item } => owned(&[module, function, item]),
+        TransferredParam { module, function, param } => owned(&[module, function, param]),
+        InvalidVersion { item, version } => item_path(item).chain([version.clone()]).collect(),
+        UnknownReplacement { item, replacement } => item_path(item).chain([replacement.clone()]).collect(),
+    }
+}
+
+/// "function calc::add" / "module calc" -> ["calc", "add"] / ["calc"]
+fn item_path(item: &str) -> impl Iterator<Item = String> + '_ {
+    let qualified = item.split_once(' ').map_or(item, |(_, name)| name);
+    qualified.split("::").map(str::to_string)
+}
+
+/// Span of the last name in `path` found by searching for each in turn; a
+/// partial match stops at the innermost item that was found.
+fn locate(text: &str, path: &[&str]) -> Option<Span<usize>> {
+    let mut found = None;
+    let mut from = 0;
+    for name in path {
+        let Some(span) = find_value(text, name, from) else { break };
+        from = span.end;
+        found = Some(span);
+    }
+    found
+}
+
+/// Each module with the text from its name to the next module's name.
+fn module_regions<'a>(text: &str, api: &'a Api) -> Vec<(&'a Module, Span<usize>)> {
+    let mut starts = Vec::new();
+    let mut from = 0;
+    for m in &api.modules {
+        if let Some(span) = find_value(text, &m.name, from) {
+            from = span.end;
+            starts.push((m, span.start));
+        }
+    }
+    let ends: Vec<usize> = starts.iter().skip(1).map(|(_, s)| *s).chain([text.len()]).collect();
+    starts.into_iter().zip(ends).map(|((m, start), end)| (m, start..end)).collect()
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_after_multi_byte_char() {
+        let doc = Document::new("doc: \"Use “add” here\"\n".to_string(), "yaml");
+        let offset = doc.text.find("add").unwrap() + 1;
+        assert_eq!(doc.word_at(offset).map(|(w, _)| w), Some("add"));
+    }
+}
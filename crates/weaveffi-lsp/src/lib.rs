@@ -0,0 +1,171 @@
+//! Language server for WeaveFFI IDL files, spoken over stdio.
+//!
+//! Publishes parse and validation diagnostics, and offers go-to-definition,
+//! hover with per-language signatures, and function rename across every
+//! open IDL document.
+
+pub mod analysis;
+
+use analysis::{is_identifier, symbols, Document, SymbolKind};
+use anyhow::{bail, Result};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, HoverRequest, Rename, Request as _};
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+    MarkupKind, OneOf, PublishDiagnosticsParams, RenameParams, ServerCapabilities, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+use std::collections::{BTreeMap, HashMap};
+use tracing::info;
+
+/// Serve LSP on stdin/stdout until the client shuts the server down.
+pub fn run_stdio() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    info!("weaveffi language server initialized");
+    main_loop(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn main_loop(connection: Connection) -> Result<()> {
+    let mut server = Server::default();
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    break;
+                }
+                connection.sender.send(server.handle_request(req).into())?;
+            }
+            Message::Notification(not) => {
+                if let Some(publish) = server.handle_notification(not)? {
+                    connection.sender.send(publish.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Server {
+    documents: BTreeMap<Url, Document>,
+}
+
+/// IDL format from the file extension, as accepted by `parse_api_str`.
+fn format_of(uri: &Url) -> String {
+    uri.path().rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default()
+}
+
+fn dispatch<R: lsp_types::request::Request>(req: Request, handler: impl FnOnce(R::Params) -> Result<R::Result>) -> Response {
+    let result = serde_json::from_value(req.params).map_err(anyhow::Error::from).and_then(handler);
+    match result.and_then(|r| Ok(serde_json::to_value(r)?)) {
+        Ok(value) => Response::new_ok(req.id, value),
+        Err(e) => Response::new_err(req.id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+impl Server {
+    fn handle_request(&self, req: Request) -> Response {
+        match req.method.as_str() {
+            GotoDefinition::METHOD => dispatch::<GotoDefinition>(req, |p| self.definition(p)),
+            HoverRequest::METHOD => dispatch::<HoverRequest>(req, |p| self.hover(p)),
+            Rename::METHOD => dispatch::<Rename>(req, |p| self.rename(p)),
+            _ => Response::new_err(req.id, ErrorCode::MethodNotFound as i32, format!("unsupported request: {}", req.method)),
+        }
+    }
+
+    /// Track document contents; returns diagnostics to publish for the affected file.
+    fn handle_notification(&mut self, not: Notification) -> Result<Option<Notification>> {
+        let uri = match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                let doc = Document::new(p.text_document.text, &format_of(&p.text_document.uri));
+                self.documents.insert(p.text_document.uri.clone(), doc);
+                p.text_document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                // Full sync: the last change carries the whole text
+                let Some(change) = p.content_changes.into_iter().last() else { return Ok(None) };
+                let doc = Document::new(change.text, &format_of(&p.text_document.uri));
+                self.documents.insert(p.text_document.uri.clone(), doc);
+                p.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                self.documents.remove(&p.text_document.uri);
+                p.text_document.uri
+            }
+            _ => return Ok(None),
+        };
+        let diagnostics = self.documents.get(&uri).map(Document::diagnostics).unwrap_or_default();
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        Ok(Some(Notification::new(PublishDiagnostics::METHOD.to_string(), params)))
+    }
+
+    fn word_at(&self, at: &TextDocumentPositionParams) -> Option<(&Document, &str, std::ops::Range<usize>)> {
+        let doc = self.documents.get(&at.text_document.uri)?;
+        let (word, span) = doc.word_at(doc.offset(at.position))?;
+        Some((doc, word, span))
+    }
+
+    fn definition(&self, p: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        let Some((_, word, _)) = self.word_at(&p.text_document_position_params) else { return Ok(None) };
+        let locations: Vec<Location> = self
+            .documents
+            .iter()
+            .flat_map(|(uri, doc)| doc.definitions(word).into_iter().map(|span| Location::new(uri.clone(), doc.range(span))))
+            .collect();
+        Ok((!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations)))
+    }
+
+    fn hover(&self, p: HoverParams) -> Result<Option<Hover>> {
+        let Some((doc, word, span)) = self.word_at(&p.text_document_position_params) else { return Ok(None) };
+        let Some(value) = doc.api().and_then(|api| analysis::hover(api, word)) else { return Ok(None) };
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: Some(doc.range(span)),
+        }))
+    }
+
+    /// Rename a function in every open document declaring the same `module::function`.
+    fn rename(&self, p: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let Some((doc, word, _)) = self.word_at(&p.text_document_position) else { return Ok(None) };
+        if !is_identifier(&p.new_name) {
+            bail!("'{}' is not a valid function name", p.new_name);
+        }
+        let Some(module) = doc
+            .api()
+            .and_then(|api| symbols(api).into_iter().find(|s| s.kind == SymbolKind::Function && s.name() == word))
+            .map(|s| s.module.name.clone())
+        else {
+            bail!("'{}' is not a function", word);
+        };
+        let mut changes = HashMap::new();
+        for (uri, doc) in &self.documents {
+            let edits: Vec<TextEdit> = doc
+                .function_references(&module, word)
+                .into_iter()
+                .map(|span| TextEdit::new(doc.range(span), p.new_name.clone()))
+                .collect();
+            if !edits.is_empty() {
+                changes.insert(uri.clone(), edits);
+            }
+        }
+        Ok(Some(WorkspaceEdit { changes: Some(changes), ..Default::default() }))
+    }
+}
@@ -9,16 +9,24 @@ type AddFn = unsafe extern "C" fn(i32, i32, *mut WeaveError) -> i32;
 type MulFn = unsafe extern "C" fn(i32, i32, *mut WeaveError) -> i32;
 type DivFn = unsafe extern "C" fn(i32, i32, *mut WeaveError) -> i32;
 type EchoFn = unsafe extern "C" fn(*const u8, usize, *mut WeaveError) -> *const c_char;
+type ReverseFn = unsafe extern "C" fn(*const u8, usize, *mut usize, *mut WeaveError) -> *const u8;
 type FreeStringFn = unsafe extern "C" fn(*const c_char);
+type FreeBytesFn = unsafe extern "C" fn(*mut u8, usize);
 type ErrorClearFn = unsafe extern "C" fn(*mut WeaveError);
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type ApiHashFn = unsafe extern "C" fn() -> u64;
 
 struct FfiApi {
   add: AddFn,
   mul: MulFn,
   div: DivFn,
   echo: EchoFn,
+  reverse: ReverseFn,
   free_string: FreeStringFn,
+  free_bytes: FreeBytesFn,
   error_clear: ErrorClearFn,
+  abi_version: AbiVersionFn,
+  api_hash: ApiHashFn,
 }
 
 static API: OnceCell<(Library, FfiApi)> = OnceCell::new();
@@ -39,9 +47,13 @@ fn load_api() -> napi::Result<&'static (Library, FfiApi)> {
       let mul: Symbol<MulFn> = lib.get(b"weaveffi_calculator_mul").map_err(map_err)?;
       let div: Symbol<DivFn> = lib.get(b"weaveffi_calculator_div").map_err(map_err)?;
       let echo: Symbol<EchoFn> = lib.get(b"weaveffi_calculator_echo").map_err(map_err)?;
+      let reverse: Symbol<ReverseFn> = lib.get(b"weaveffi_calculator_reverse").map_err(map_err)?;
       let free_string: Symbol<FreeStringFn> = lib.get(b"weaveffi_free_string").map_err(map_err)?;
+      let free_bytes: Symbol<FreeBytesFn> = lib.get(b"weaveffi_free_bytes").map_err(map_err)?;
       let error_clear: Symbol<ErrorClearFn> = lib.get(b"weaveffi_error_clear").map_err(map_err)?;
-      FfiApi { add: *add, mul: *mul, div: *div, echo: *echo, free_string: *free_string, error_clear: *error_clear }
+      let abi_version: Symbol<AbiVersionFn> = lib.get(b"weaveffi_abi_version").map_err(map_err)?;
+      let api_hash: Symbol<ApiHashFn> = lib.get(b"weaveffi_api_hash").map_err(map_err)?;
+      FfiApi { add: *add, mul: *mul, div: *div, echo: *echo, reverse: *reverse, free_string: *free_string, free_bytes: *free_bytes, error_clear: *error_clear, abi_version: *abi_version, api_hash: *api_hash }
     };
     Ok((lib, api))
   })
@@ -65,6 +77,19 @@ fn take_error(err: &mut WeaveError) -> Option<(i32, String)> {
   Some((err.code, msg))
 }
 
+#[napi]
+pub fn abi_version() -> napi::Result<u32> {
+  let (_, api) = load_api()?;
+  Ok(unsafe { (api.abi_version)() })
+}
+
+/// Hex string (`0x...`): JS numbers cannot hold every u64.
+#[napi]
+pub fn api_hash() -> napi::Result<String> {
+  let (_, api) = load_api()?;
+  Ok(format!("0x{:016x}", unsafe { (api.api_hash)() }))
+}
+
 #[napi]
 pub fn add(a: i32, b: i32) -> napi::Result<i32> {
   let mut err = WeaveError { code: 0, message: std::ptr::null() };
@@ -107,3 +132,24 @@ pub fn echo(s: String) -> napi::Result<String> {
   unsafe { (api.free_string)(c_ptr) };
   Ok(out)
 }
+
+/// `data` is passed as a view of the JS Buffer and the result is adopted as an
+/// external Buffer, freed with `weaveffi_free_bytes` when it is collected.
+#[napi]
+pub fn reverse<'env>(env: &'env Env, data: BufferSlice) -> napi::Result<BufferSlice<'env>> {
+  let mut err = WeaveError { code: 0, message: std::ptr::null() };
+  let (_, api) = load_api()?;
+  let mut len = 0usize;
+  let ptr = unsafe { (api.reverse)(data.as_ptr(), data.len(), &mut len, &mut err as *mut WeaveError) };
+  if let Some((code, msg)) = take_error(&mut err) { return Err(Error::new(Status::GenericFailure, format!("({}) {}", code, msg))); }
+  if ptr.is_null() { return Err(Error::new(Status::GenericFailure, "null bytes".to_string())); }
+  let ptr = ptr as *mut u8;
+  let free_bytes = api.free_bytes;
+  if len == 0 {
+    // N-API rejects empty external buffers
+    unsafe { free_bytes(ptr, len) };
+    return BufferSlice::from_data(env, Vec::new());
+  }
+  // SAFETY: the library hands over `len` bytes at `ptr`; the finalizer frees them exactly once
+  unsafe { BufferSlice::from_external(env, ptr, len, len, move |_, len| free_bytes(ptr, len)) }
+}
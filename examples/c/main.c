@@ -4,6 +4,11 @@
 #include "../../generated/c/weaveffi.h"
 
 int main() {
+    if (!weaveffi_check_compat()) {
+        printf("library does not match weaveffi.h (ABI %u, API hash %llx)\n", weaveffi_abi_version(), (unsigned long long)weaveffi_api_hash());
+        return 1;
+    }
+
     struct weaveffi_error err = {0};
 
     int32_t sum = weaveffi_calculator_add(3, 4, &err);
@@ -11,4 +11,5 @@ console.log('add(3,4) =', api.add(3, 4))
 console.log('mul(5,6) =', api.mul(5, 6))
 console.log('div(10,2) =', api.div(10, 2))
 console.log('echo("hello") =', api.echo('hello'))
+console.log('reverse([1,2,3]) =', api.reverse(Buffer.from([1, 2, 3])))
 try { api.div(1, 0) } catch (e) { console.log('div(1,0) error =', String(e)) }
@@ -0,0 +1,13 @@
+// Derive the API hash from the IDL so `weaveffi_api_hash` always matches the
+// bindings generated from the same file.
+use std::path::PathBuf;
+
+fn main() {
+    let idl = "calculator.yml";
+    println!("cargo:rerun-if-changed={}", idl);
+    let text = std::fs::read_to_string(idl).expect("read calculator.yml");
+    let api = weaveffi_ir::parse::parse_api_str(&text, "yaml").expect("parse calculator.yml");
+    let hash = weaveffi_core::compat::api_hash(&api);
+    let out = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("api_hash.rs");
+    std::fs::write(out, format!("const API_HASH: u64 = 0x{:016x};\n", hash)).expect("write api_hash.rs");
+}
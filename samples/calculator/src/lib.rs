@@ -1,20 +1,38 @@
+//! Exported shims follow the contract in the generated `weaveffi.h`: every
+//! pointer argument is null or valid for the duration of the call.
+
+use std::ffi::c_void;
 use std::os::raw::c_char;
 use weaveffi_core::abi::{self, weaveffi_error};
 
+include!(concat!(env!("OUT_DIR"), "/api_hash.rs"));
+
+#[no_mangle]
+pub extern "C" fn weaveffi_abi_version() -> u32 { abi::ABI_VERSION }
+
 #[no_mangle]
-pub extern "C" fn weaveffi_calculator_add(a: i32, b: i32, out_err: *mut weaveffi_error) -> i32 {
+pub extern "C" fn weaveffi_api_hash() -> u64 { API_HASH }
+
+/// # Safety
+/// `out_err` must be null or a valid error.
+#[no_mangle]
+pub unsafe extern "C" fn weaveffi_calculator_add(a: i32, b: i32, out_err: *mut weaveffi_error) -> i32 {
     abi::error_set_ok(out_err);
     a + b
 }
 
+/// # Safety
+/// `out_err` must be null or a valid error.
 #[no_mangle]
-pub extern "C" fn weaveffi_calculator_mul(a: i32, b: i32, out_err: *mut weaveffi_error) -> i32 {
+pub unsafe extern "C" fn weaveffi_calculator_mul(a: i32, b: i32, out_err: *mut weaveffi_error) -> i32 {
     abi::error_set_ok(out_err);
     a * b
 }
 
+/// # Safety
+/// `out_err` must be null or a valid error.
 #[no_mangle]
-pub extern "C" fn weaveffi_calculator_div(a: i32, b: i32, out_err: *mut weaveffi_error) -> i32 {
+pub unsafe extern "C" fn weaveffi_calculator_div(a: i32, b: i32, out_err: *mut weaveffi_error) -> i32 {
     if b == 0 {
         abi::error_set(out_err, 2, "division by zero");
         return 0;
@@ -23,11 +41,12 @@ pub extern "C" fn weaveffi_calculator_div(a: i32, b: i32, out_err: *mut weaveffi
     a / b
 }
 
+/// # Safety
+/// `ptr` must point to `len` readable bytes and `out_err` must be null or a valid error.
 #[no_mangle]
-pub extern "C" fn weaveffi_calculator_echo(ptr: *const u8, len: usize, out_err: *mut weaveffi_error) -> *const c_char {
-    // Safety contract: ptr must be valid bytes of length len
-    let slice = unsafe { std::slice::from_raw_parts(ptr, len) };
-    let s = match String::from_utf8(slice.to_vec()) {
+pub unsafe extern "C" fn weaveffi_calculator_echo(ptr: *const u8, len: usize, out_err: *mut weaveffi_error) -> *const c_char {
+    // Safety contract: ptr must be valid bytes of length len; borrowed, no copy
+    let s = match abi::borrow_str(ptr, len) {
         Ok(v) => v,
         Err(e) => {
             abi::error_set(out_err, 1, &format!("invalid UTF-8: {}", e));
@@ -35,15 +54,43 @@ pub extern "C" fn weaveffi_calculator_echo(ptr: *const u8, len: usize, out_err:
         }
     };
     abi::error_set_ok(out_err);
-    abi::string_to_c_ptr(s)
+    abi::string_to_c_ptr(s, out_err)
+}
+
+/// # Safety
+/// `data_ptr` must point to `data_len` readable bytes, `out_len` must be valid for writes
+/// and `out_err` must be null or a valid error.
+#[no_mangle]
+pub unsafe extern "C" fn weaveffi_calculator_reverse(data_ptr: *const u8, data_len: usize, out_len: *mut usize, out_err: *mut weaveffi_error) -> *const u8 {
+    let mut reversed = abi::borrow_bytes(data_ptr, data_len).to_vec();
+    reversed.reverse();
+    abi::error_set_ok(out_err);
+    abi::bytes_to_c_ptr(reversed, out_len, out_err)
 }
 
 // Expose free helpers and error clear to conform to the ABI requirements.
+/// # Safety
+/// `ptr_` must be null or a string returned by this library that was not freed yet.
 #[no_mangle]
-pub extern "C" fn weaveffi_free_string(ptr_: *const c_char) { abi::free_string(ptr_) }
+pub unsafe extern "C" fn weaveffi_free_string(ptr_: *const c_char) { abi::free_string(ptr_) }
 
+/// # Safety
+/// `ptr` must be null or a buffer returned by this library together with `len`.
 #[no_mangle]
-pub extern "C" fn weaveffi_free_bytes(ptr: *mut u8, len: usize) { abi::free_bytes(ptr, len) }
+pub unsafe extern "C" fn weaveffi_free_bytes(ptr: *mut u8, len: usize) { abi::free_bytes(ptr, len) }
 
+/// # Safety
+/// `err` must be null or a valid error.
 #[no_mangle]
-pub extern "C" fn weaveffi_error_clear(err: *mut weaveffi_error) { abi::error_clear(err) }
+pub unsafe extern "C" fn weaveffi_error_clear(err: *mut weaveffi_error) { abi::error_clear(err) }
+
+/// # Safety
+/// The callbacks must behave like `malloc` and a sized `free` from any thread.
+#[no_mangle]
+pub unsafe extern "C" fn weaveffi_set_allocator(
+    malloc_fn: abi::weaveffi_malloc_fn,
+    free_fn: abi::weaveffi_free_fn,
+    user_data: *mut c_void,
+) -> bool {
+    abi::set_allocator(malloc_fn, free_fn, user_data)
+}